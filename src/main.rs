@@ -20,6 +20,21 @@ fn main() {
         process::exit(1)
     }
 
+    let options: Vec<(String, Option<String>)> = std::env::args()
+        .skip(2)
+        .map(|arg| match arg.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        })
+        .collect();
+
+    for (name, _) in &options {
+        if solutions::find_option(day, name).is_none() {
+            println!("Unknown option {} for Day #{}!", name, day);
+            process::exit(1)
+        }
+    }
+
     let input_file_str = format!("./input/day{}.txt", day);
     let input_file_path = Path::new(&input_file_str);
 
@@ -35,4 +50,9 @@ fn main() {
     let (part1, part2) = solutions::DAYS[day](&input);
 
     println!("Part 1: {}, Part 2: {}", part1, part2);
+
+    for (name, value) in options {
+        let option = solutions::find_option(day, &name).unwrap();
+        println!("{}", option(&input, value.as_deref()));
+    }
 }
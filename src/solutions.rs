@@ -14,9 +14,15 @@ mod day12;
 mod day13;
 mod day14;
 mod day15;
+mod ocr;
+mod render;
+mod search;
 
 type Solution = fn(&str) -> (String, String);
 
+// Extra output for a day, given the input and the value after `=` if any
+type DayOption = fn(&str, Option<&str>) -> String;
+
 pub static DAYS: [Solution; 16] = [
     day0::day0,
     day1::day1,
@@ -35,3 +41,31 @@ pub static DAYS: [Solution; 16] = [
     day14::day14,
     day15::day15,
 ];
+
+pub static OPTIONS: &[(usize, &str, DayOption)] = &[
+    (9, "--frames", day9::frames_option),
+    (9, "--ppm", day9::ppm_option),
+    (10, "--render", day10::render_option),
+    (11, "--trace", day11::trace_option),
+    (11, "--histogram", day11::histogram_option),
+    (12, "--path", day12::path_option),
+    (12, "--path-ppm", day12::path_ppm_option),
+    (12, "--report", day12::report_option),
+    (13, "--pretty", day13::pretty_option),
+    (13, "--json", day13::json_option),
+    (13, "--explain", day13::explain_option),
+    (13, "--probes", day13::probes_option),
+    (14, "--verify", day14::verify_option),
+    (14, "--render", day14::render_option),
+    (14, "--animate", day14::animate_option),
+    (14, "--animate-ppm", day14::animate_ppm_option),
+    (14, "--variant", day14::variant_option),
+    (15, "--verify", day15::verify_option),
+];
+
+pub fn find_option(day: usize, name: &str) -> Option<DayOption> {
+    OPTIONS
+        .iter()
+        .find(|(option_day, option_name, _)| *option_day == day && *option_name == name)
+        .map(|(_, _, option)| *option)
+}
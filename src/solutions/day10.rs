@@ -1,66 +1,250 @@
-use std::collections::HashSet;
+use super::ocr;
+use std::collections::HashMap;
 
-pub fn day10(input: &str) -> (String, String) {
-    let instructions: Vec<(&str, Option<isize>)> = input
-        .trim()
-        .lines()
-        .map(|line| line.trim())
-        .map(|line| line.split_whitespace().collect::<Vec<&str>>())
-        .map(|parts| match parts.as_slice() {
-            [instr] => (*instr, None),
-            [instr, num] => (*instr, num.parse::<isize>().ok()),
-            _ => panic!(),
-        })
-        .collect();
-
-    let interesting_cycles: HashSet<isize> = HashSet::from([20, 60, 100, 140, 180, 220]);
-    let line_cycles: HashSet<isize> = HashSet::from([40, 80, 120, 160, 200, 240]);
-
-    let mut cycles: Vec<(isize, isize)> = Vec::new();
-    cycles.push((1, 1));
-
-    for instr in instructions {
-        let &(c, x) = cycles.last().unwrap();
-
-        match instr {
-            ("noop", _) => {
-                cycles.push((c + 1, x));
-            }
-            ("addx", Some(v)) => {
-                cycles.push((c + 1, x));
-                cycles.push((c + 2, x + v));
+#[derive(Debug, PartialEq)]
+enum CpuError {
+    UnknownInstruction(String),
+    WrongArity(String),
+    InvalidOperand(String),
+}
+
+type Registers = HashMap<char, isize>;
+
+type CycleHook<'a> = Box<dyn FnMut(usize, &Registers) + 'a>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Value(isize),
+    Register(char),
+}
+
+impl Operand {
+    fn parse(operand: &str) -> Option<Operand> {
+        if let Ok(value) = operand.parse::<isize>() {
+            return Some(Operand::Value(value));
+        }
+
+        match operand.chars().collect::<Vec<char>>()[..] {
+            [name] if name.is_ascii_alphabetic() => Some(Operand::Register(name)),
+            _ => None,
+        }
+    }
+
+    fn value(&self, registers: &Registers) -> isize {
+        match self {
+            Operand::Value(value) => *value,
+            Operand::Register(name) => *registers.get(name).unwrap_or(&0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Instruction {
+    name: String,
+    operands: Vec<Operand>,
+}
+
+// How an instruction is decoded and how long it takes. `execute` runs at the
+// end of the instruction's last cycle and returns the jump to the next
+// instruction, 1 being the one after it.
+#[derive(Clone, Copy)]
+struct InstructionSpec {
+    arity: usize,
+    cycles: usize,
+    execute: fn(&mut Registers, &[Operand]) -> isize,
+}
+
+struct Cpu<'a> {
+    registers: Registers,
+    cycle: usize,
+    instruction_set: HashMap<String, InstructionSpec>,
+    hooks: Vec<CycleHook<'a>>,
+}
+
+impl<'a> Cpu<'a> {
+    fn new() -> Cpu<'a> {
+        Cpu {
+            registers: HashMap::new(),
+            cycle: 0,
+            instruction_set: HashMap::new(),
+            hooks: Vec::new(),
+        }
+    }
+
+    // The handheld device from the puzzle, with a single X register
+    fn handheld() -> Cpu<'a> {
+        let mut cpu = Cpu::new();
+
+        cpu.registers.insert('X', 1);
+
+        cpu.register_instruction(
+            "noop",
+            InstructionSpec {
+                arity: 0,
+                cycles: 1,
+                execute: |_, _| 1,
+            },
+        );
+
+        cpu.register_instruction(
+            "addx",
+            InstructionSpec {
+                arity: 1,
+                cycles: 2,
+                execute: |registers, operands| {
+                    let value = operands[0].value(registers);
+                    *registers.entry('X').or_insert(0) += value;
+                    1
+                },
+            },
+        );
+
+        cpu
+    }
+
+    fn register_instruction(&mut self, name: &str, spec: InstructionSpec) {
+        self.instruction_set.insert(name.to_string(), spec);
+    }
+
+    // Called during every cycle with the cycle number, starting at 1, and
+    // the register values before that cycle's instruction completes
+    fn add_hook(&mut self, hook: impl FnMut(usize, &Registers) + 'a) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    fn parse_program(&self, input: &str) -> Result<Vec<Instruction>, CpuError> {
+        input
+            .trim()
+            .lines()
+            .map(|line| line.trim())
+            .map(|line| {
+                let mut parts = line.split_whitespace();
+                let name = parts.next().unwrap_or_default();
+
+                let spec = self
+                    .instruction_set
+                    .get(name)
+                    .ok_or_else(|| CpuError::UnknownInstruction(line.to_string()))?;
+
+                let operands = parts
+                    .map(|operand| {
+                        Operand::parse(operand)
+                            .ok_or_else(|| CpuError::InvalidOperand(line.to_string()))
+                    })
+                    .collect::<Result<Vec<Operand>, CpuError>>()?;
+
+                if operands.len() != spec.arity {
+                    return Err(CpuError::WrongArity(line.to_string()));
+                }
+
+                Ok(Instruction {
+                    name: name.to_string(),
+                    operands,
+                })
+            })
+            .collect()
+    }
+
+    fn run(&mut self, program: &[Instruction]) {
+        let mut pc: isize = 0;
+
+        while let Some(instr) = usize::try_from(pc).ok().and_then(|pc| program.get(pc)) {
+            pc += self.step(instr);
+        }
+    }
+
+    fn step(&mut self, instr: &Instruction) -> isize {
+        let spec = self.instruction_set[&instr.name];
+
+        for _ in 0..spec.cycles {
+            self.cycle += 1;
+
+            for hook in self.hooks.iter_mut() {
+                hook(self.cycle, &self.registers);
             }
-            _ => panic!(),
         }
+
+        (spec.execute)(&mut self.registers, &instr.operands)
     }
+}
 
-    let part1 = cycles
-        .iter()
-        .filter(|(c, _)| interesting_cycles.contains(c))
-        .map(|&(c, x)| c * x)
-        .sum::<isize>()
-        .to_string();
+const CRT_WIDTH: usize = 40;
+const CRT_HEIGHT: usize = 6;
 
-    let mut part2: String = String::new();
-    part2.push('\n');
+#[derive(Debug, PartialEq)]
+struct Crt {
+    pixels: [[bool; CRT_WIDTH]; CRT_HEIGHT],
+}
 
-    for (c, x) in cycles {
-        let p = c % 40;
-        let out = if p == x || p == x + 1 || p == x + 2 {
-            '█'
-        } else {
-            ' '
-        };
+impl Crt {
+    fn new() -> Crt {
+        Crt {
+            pixels: [[false; CRT_WIDTH]; CRT_HEIGHT],
+        }
+    }
+
+    // Cycle 1 draws the top left pixel, lit when the 3 pixel wide sprite
+    // centred on X overlaps it. Cycles past the last pixel draw nothing.
+    fn draw(&mut self, cycle: usize, sprite_x: isize) {
+        let (row, col) = ((cycle - 1) / CRT_WIDTH, (cycle - 1) % CRT_WIDTH);
 
-        part2.push(out);
-        if line_cycles.contains(&c) {
-            part2.push('\n');
+        if let Some(pixels) = self.pixels.get_mut(row) {
+            pixels[col] = (col as isize).abs_diff(sprite_x) <= 1;
         }
     }
 
+    fn render(&self) -> String {
+        self.pixels
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&lit| if lit { '█' } else { ' ' })
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+// Runs the program on the handheld, returning the summed signal strength and
+// what was drawn on the CRT
+fn run_device(input: &str) -> (isize, Crt) {
+    let mut signal_strength: isize = 0;
+    let mut crt = Crt::new();
+
+    {
+        let mut cpu = Cpu::handheld();
+        let program = cpu.parse_program(input).unwrap();
+
+        cpu.add_hook(|cycle, registers| {
+            if cycle % 40 == 20 {
+                signal_strength += cycle as isize * registers[&'X'];
+            }
+        });
+
+        cpu.add_hook(|cycle, registers| crt.draw(cycle, registers[&'X']));
+
+        cpu.run(&program);
+    }
+
+    (signal_strength, crt)
+}
+
+pub fn day10(input: &str) -> (String, String) {
+    let (signal_strength, crt) = run_device(input);
+
+    let part1 = signal_strength.to_string();
+    let part2 = ocr::read_letters(&crt.pixels);
+
     (part1, part2)
 }
 
+pub fn render_option(input: &str, _value: Option<&str>) -> String {
+    let (_, crt) = run_device(input);
+
+    crt.render()
+}
+
 #[cfg(test)]
 mod tests {
     const INPUT: &str = "
@@ -213,8 +397,122 @@ mod tests {
 
     #[test]
     fn test_day10() {
-        let (part1, _) = super::day10(INPUT);
+        let (part1, part2) = super::day10(INPUT);
 
         assert_eq!(part1, "13140");
+        assert_eq!(part2, "????????");
+    }
+
+    #[test]
+    fn test_day10_crt_image() {
+        let image = [
+            "##..##..##..##..##..##..##..##..##..##..",
+            "###...###...###...###...###...###...###.",
+            "####....####....####....####....####....",
+            "#####.....#####.....#####.....#####.....",
+            "######......######......######......####",
+            "#######.......#######.......#######.....",
+        ];
+
+        let (_, crt) = super::run_device(INPUT);
+
+        let drawn: Vec<String> = crt
+            .pixels
+            .iter()
+            .map(|row| row.iter().map(|&lit| if lit { '#' } else { '.' }).collect())
+            .collect();
+        assert_eq!(drawn, image);
+
+        assert_eq!(crt.render().lines().count(), 6);
+    }
+
+    #[test]
+    fn test_day10_cpu_cycles() {
+        use super::Cpu;
+
+        let mut seen: Vec<(usize, isize)> = Vec::new();
+
+        {
+            let mut cpu = Cpu::handheld();
+            let program = cpu.parse_program("noop\naddx 3\naddx -5").unwrap();
+
+            cpu.add_hook(|cycle, registers| seen.push((cycle, registers[&'X'])));
+            cpu.run(&program);
+
+            assert_eq!(cpu.cycle, 5);
+            assert_eq!(cpu.registers[&'X'], -1);
+        }
+
+        assert_eq!(seen, vec![(1, 1), (2, 1), (3, 1), (4, 4), (5, 4)]);
+    }
+
+    #[test]
+    fn test_day10_cpu_custom_instructions() {
+        use super::{Cpu, InstructionSpec};
+
+        let mut cpu = Cpu::new();
+
+        cpu.register_instruction(
+            "cpy",
+            InstructionSpec {
+                arity: 2,
+                cycles: 1,
+                execute: |registers, operands| {
+                    if let super::Operand::Register(name) = operands[1] {
+                        registers.insert(name, operands[0].value(registers));
+                    }
+                    1
+                },
+            },
+        );
+
+        cpu.register_instruction(
+            "dec",
+            InstructionSpec {
+                arity: 1,
+                cycles: 1,
+                execute: |registers, operands| {
+                    if let super::Operand::Register(name) = operands[0] {
+                        *registers.entry(name).or_insert(0) -= 1;
+                    }
+                    1
+                },
+            },
+        );
+
+        cpu.register_instruction(
+            "jnz",
+            InstructionSpec {
+                arity: 2,
+                cycles: 3,
+                execute: |registers, operands| match operands[0].value(registers) {
+                    0 => 1,
+                    _ => operands[1].value(registers),
+                },
+            },
+        );
+
+        let program = cpu.parse_program("cpy 4 a\ndec a\njnz a -1").unwrap();
+        cpu.run(&program);
+
+        assert_eq!(cpu.registers[&'a'], 0);
+        assert_eq!(cpu.cycle, 1 + 4 * (1 + 3));
+    }
+
+    #[test]
+    fn test_day10_cpu_invalid_program() {
+        use super::Cpu;
+        use super::CpuError::{InvalidOperand, UnknownInstruction, WrongArity};
+
+        let cpu = Cpu::handheld();
+
+        let err = cpu.parse_program("mulx 3").unwrap_err();
+        assert_eq!(err, UnknownInstruction("mulx 3".to_string()));
+
+        let err = cpu.parse_program("addx").unwrap_err();
+        assert_eq!(err, WrongArity("addx".to_string()));
+
+        let err = cpu.parse_program("addx 1.5").unwrap_err();
+        assert_eq!(err, InvalidOperand("addx 1.5".to_string()));
     }
 }
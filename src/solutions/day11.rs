@@ -1,149 +1,630 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+#[derive(Debug, PartialEq)]
+enum ExprError {
+    UnknownToken(String),
+    UnexpectedToken(String),
+    UnexpectedEnd,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(usize),
+    Old,
+    Op(char),
+    Open,
+    Close,
+}
+
+// Right hand side of a monkey's `new = ...` operation
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Num(usize),
+    Old,
+    BinOp(Box<Expr>, char, Box<Expr>),
+}
+
+impl Expr {
+    fn parse(input: &str) -> Result<Expr, ExprError> {
+        let mut tokens: VecDeque<Token> = Self::tokenize(input)?;
+        let expr = Self::parse_sum(&mut tokens)?;
+
+        match tokens.pop_front() {
+            None => Ok(expr),
+            Some(token) => Err(ExprError::UnexpectedToken(format!("{:?}", token))),
+        }
+    }
+
+    fn tokenize(input: &str) -> Result<VecDeque<Token>, ExprError> {
+        let mut tokens: VecDeque<Token> = VecDeque::new();
+        let mut chars = input.chars().peekable();
+
+        while let Some(&ch) = chars.peek() {
+            match ch {
+                ' ' => {
+                    chars.next();
+                }
+                '+' | '-' | '*' | '/' | '%' => {
+                    tokens.push_back(Token::Op(ch));
+                    chars.next();
+                }
+                '(' => {
+                    tokens.push_back(Token::Open);
+                    chars.next();
+                }
+                ')' => {
+                    tokens.push_back(Token::Close);
+                    chars.next();
+                }
+                _ if ch.is_ascii_alphanumeric() => {
+                    let mut word = String::new();
+                    while let Some(&ch) = chars.peek().filter(|ch| ch.is_ascii_alphanumeric()) {
+                        word.push(ch);
+                        chars.next();
+                    }
+
+                    let token = match word.parse::<usize>() {
+                        Ok(num) => Token::Num(num),
+                        Err(_) if word == "old" => Token::Old,
+                        Err(_) => return Err(ExprError::UnknownToken(word)),
+                    };
+                    tokens.push_back(token);
+                }
+                _ => return Err(ExprError::UnknownToken(ch.to_string())),
+            }
+        }
+
+        Ok(tokens)
+    }
+
+    // sum = product (('+' | '-') product)*
+    fn parse_sum(tokens: &mut VecDeque<Token>) -> Result<Expr, ExprError> {
+        let mut lhs = Self::parse_product(tokens)?;
+
+        while let Some(Token::Op(op @ ('+' | '-'))) = tokens.front().cloned() {
+            tokens.pop_front();
+            let rhs = Self::parse_product(tokens)?;
+            lhs = Expr::BinOp(Box::new(lhs), op, Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    // product = atom (('*' | '/' | '%') atom)*
+    fn parse_product(tokens: &mut VecDeque<Token>) -> Result<Expr, ExprError> {
+        let mut lhs = Self::parse_atom(tokens)?;
+
+        while let Some(Token::Op(op @ ('*' | '/' | '%'))) = tokens.front().cloned() {
+            tokens.pop_front();
+            let rhs = Self::parse_atom(tokens)?;
+            lhs = Expr::BinOp(Box::new(lhs), op, Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    // atom = number | 'old' | '(' sum ')'
+    fn parse_atom(tokens: &mut VecDeque<Token>) -> Result<Expr, ExprError> {
+        match tokens.pop_front() {
+            Some(Token::Num(num)) => Ok(Expr::Num(num)),
+            Some(Token::Old) => Ok(Expr::Old),
+            Some(Token::Open) => {
+                let inner = Self::parse_sum(tokens)?;
+                match tokens.pop_front() {
+                    Some(Token::Close) => Ok(inner),
+                    Some(token) => Err(ExprError::UnexpectedToken(format!("{:?}", token))),
+                    None => Err(ExprError::UnexpectedEnd),
+                }
+            }
+            Some(token) => Err(ExprError::UnexpectedToken(format!("{:?}", token))),
+            None => Err(ExprError::UnexpectedEnd),
+        }
+    }
+
+    // Division and remainder don't survive reducing worry by a modulus
+    fn is_modular(&self) -> bool {
+        match self {
+            Expr::Num(_) | Expr::Old => true,
+            Expr::BinOp(lhs, op, rhs) => {
+                !matches!(op, '/' | '%') && lhs.is_modular() && rhs.is_modular()
+            }
+        }
+    }
+
+    // None on overflow, underflow or division by zero. With a modulus,
+    // every operand and result is reduced by it, so only division by zero
+    // fails and subtraction wraps around it instead of going below zero.
+    fn eval(&self, old: usize, modulus: Option<usize>) -> Option<usize> {
+        let reduce = |num: usize| modulus.map_or(num, |m| num % m);
+
+        match self {
+            Expr::Num(num) => Some(reduce(*num)),
+            Expr::Old => Some(reduce(old)),
+            Expr::BinOp(lhs, op, rhs) => {
+                let lhs = lhs.eval(old, modulus)?;
+                let rhs = rhs.eval(old, modulus)?;
+
+                match (op, modulus) {
+                    ('+', None) => lhs.checked_add(rhs),
+                    ('-', None) => lhs.checked_sub(rhs),
+                    ('*', None) => lhs.checked_mul(rhs),
+                    // Both sides are below m, so these fit in a u128
+                    ('+' | '-' | '*', Some(m)) => {
+                        let (lhs, rhs, m) = (lhs as u128, rhs as u128, m as u128);
+                        let result = match op {
+                            '+' => lhs + rhs,
+                            '-' => lhs + m - rhs,
+                            _ => lhs * rhs,
+                        };
+                        Some((result % m) as usize)
+                    }
+                    ('/', _) => lhs.checked_div(rhs),
+                    ('%', _) => lhs.checked_rem(rhs),
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum MonkeyError {
+    MissingField { monkey: usize, field: &'static str },
+    InvalidLine(String),
+    InvalidOperation(usize, ExprError),
+    DuplicateMonkey(usize),
+    UnknownTarget { monkey: usize, target: usize },
+    SelfTarget(usize),
+    ZeroDivisor(usize),
+}
+
+#[derive(Debug, PartialEq)]
+enum RoundError {
+    WorryOverflow { monkey: usize, worry: usize },
+    UnboundedWorry,
+}
+
+// One item being inspected and thrown, with worry before the inspection and
+// after being reduced. Monkeys are given by id.
+#[derive(Debug, Clone, PartialEq)]
+struct Throw {
+    round: usize,
+    monkey: usize,
+    worry_before: usize,
+    worry_after: usize,
+    target: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Monkey {
+    id: usize,
+    items: VecDeque<usize>,
+    operation: Expr,
+    div_test: usize,
+    // Indexes into Monkies::monkeys, which are ordered by id
+    if_true: usize,
+    if_false: usize,
+    inspections: usize,
+}
+
+impl Monkey {
+    fn throw_target(&self, worry: usize) -> usize {
+        match worry % self.div_test {
+            0 => self.if_true,
+            _ => self.if_false,
+        }
+    }
+}
 
 #[derive(Debug)]
 struct Monkies {
-    items: Vec<VecDeque<usize>>,
-    op_operators: Vec<char>,
-    op_values: Vec<Option<usize>>,
-    div_tests: Vec<usize>,
-    true_indexes: Vec<usize>,
-    false_indexes: Vec<usize>,
-    businesses: Vec<usize>,
-    gcd: usize, // Assume all div check number are prime so gcd is just product
+    monkeys: Vec<Monkey>,
+    modulus: Option<usize>,
+    rounds: usize,
+    trace: Option<Vec<Throw>>,
 }
 
 impl Monkies {
-    fn parse(input: &str) -> Monkies {
-        let mut items: Vec<VecDeque<usize>> = Vec::new();
-        let mut op_operators: Vec<char> = Vec::new();
-        let mut op_values: Vec<Option<usize>> = Vec::new();
-        let mut div_tests: Vec<usize> = Vec::new();
-        let mut true_indexes: Vec<usize> = Vec::new();
-        let mut false_indexes: Vec<usize> = Vec::new();
-        let mut businesses: Vec<usize> = Vec::new();
-
-        let monkey_inputs: Vec<Vec<&str>> = input
-            .split("\n\n")
-            .map(|group| group.lines().collect::<Vec<&str>>())
-            .map(|lines| {
+    // Fields are found by their label, so lines may come in any order and
+    // unrecognised lines are skipped. Monkey ids don't need to be contiguous.
+    fn parse(input: &str) -> Result<Monkies, MonkeyError> {
+        // Throw targets are still ids at this point
+        let mut monkeys: Vec<Monkey> = Vec::new();
+        let mut blocks: Vec<(usize, Vec<&str>)> = Vec::new();
+
+        for line in input.lines().map(|line| line.trim()) {
+            if let Some(header) = line.strip_prefix("Monkey ") {
+                let id = header
+                    .trim_end_matches(':')
+                    .parse::<usize>()
+                    .map_err(|_| MonkeyError::InvalidLine(line.to_string()))?;
+                blocks.push((id, Vec::new()));
+            } else if let Some((_, lines)) = blocks.last_mut() {
+                lines.push(line);
+            }
+        }
+
+        for (id, lines) in blocks {
+            if monkeys.iter().any(|monkey| monkey.id == id) {
+                return Err(MonkeyError::DuplicateMonkey(id));
+            }
+
+            let field = |label: &'static str| {
                 lines
-                    .into_iter()
-                    .map(|line| line.trim())
-                    .collect::<Vec<&str>>()
-            })
-            .collect();
+                    .iter()
+                    .find_map(|line| line.strip_prefix(label))
+                    .map(|value| value.trim())
+                    .ok_or(MonkeyError::MissingField {
+                        monkey: id,
+                        field: label,
+                    })
+            };
+
+            let number = |label: &'static str, prefix: &str| {
+                let value = field(label)?;
+                value
+                    .strip_prefix(prefix)
+                    .and_then(|num| num.trim().parse::<usize>().ok())
+                    .ok_or_else(|| MonkeyError::InvalidLine(format!("{} {}", label, value)))
+            };
 
-        for monkey_lines in monkey_inputs {
-            let starting_items: VecDeque<usize> = monkey_lines[1]
-                .trim_start_matches("Starting items: ")
+            let items = field("Starting items:")?
                 .split(',')
-                .filter_map(|piece| piece.trim().parse().ok())
-                .collect();
-
-            let operator: char = monkey_lines[2]
-                .trim_start_matches("Operation: new = old ")
-                .split_whitespace()
-                .next()
-                .unwrap()
-                .chars()
-                .next()
-                .unwrap();
-
-            let value: Option<usize> = monkey_lines[2]
-                .trim_start_matches("Operation: new = old ")
-                .split_whitespace()
-                .last()
-                .unwrap()
-                .parse::<usize>()
-                .ok();
-
-            let div_test: usize = monkey_lines[3]
-                .trim_start_matches("Test: divisible by ")
-                .parse()
-                .unwrap();
-
-            let monkey_true: usize = monkey_lines[4]
-                .trim_start_matches("If true: throw to monkey ")
-                .parse()
-                .unwrap();
-
-            let monkey_false: usize = monkey_lines[5]
-                .trim_start_matches("If false: throw to monkey ")
-                .parse()
-                .unwrap();
-
-            items.push(starting_items);
-            op_operators.push(operator);
-            op_values.push(value);
-            div_tests.push(div_test);
-            true_indexes.push(monkey_true);
-            false_indexes.push(monkey_false);
-            businesses.push(0);
-        }
-
-        let gcd = div_tests.iter().product();
-
-        Monkies {
-            items,
-            op_operators,
-            op_values,
-            div_tests,
-            true_indexes,
-            false_indexes,
-            businesses,
-            gcd,
-        }
-    }
-
-    fn do_round(&mut self, worry_divide: usize) {
-        for i in 0..self.items.len() {
-            while let Some(old_worry) = self.items[i].pop_front() {
-                let op_value = match self.op_values[i] {
-                    Some(val) => val,
-                    None => old_worry,
-                };
+                .map(|piece| piece.trim())
+                .filter(|piece| !piece.is_empty())
+                .map(|piece| {
+                    piece
+                        .parse::<usize>()
+                        .map_err(|_| MonkeyError::InvalidLine(format!("Starting items: {}", piece)))
+                })
+                .collect::<Result<VecDeque<usize>, MonkeyError>>()?;
 
-                let inspect_worry = match self.op_operators[i] {
-                    '*' => old_worry * op_value,
-                    '+' => old_worry + op_value,
-                    opr => panic!("Unknown opr: {}", opr),
-                };
+            let operation = field("Operation:")?;
+            let operation = operation
+                .strip_prefix("new =")
+                .ok_or_else(|| MonkeyError::InvalidLine(format!("Operation: {}", operation)))
+                .and_then(|expr| {
+                    Expr::parse(expr).map_err(|err| MonkeyError::InvalidOperation(id, err))
+                })?;
+
+            let div_test = number("Test:", "divisible by")?;
+            if div_test == 0 {
+                return Err(MonkeyError::ZeroDivisor(id));
+            }
+
+            monkeys.push(Monkey {
+                id,
+                items,
+                operation,
+                div_test,
+                if_true: number("If true:", "throw to monkey")?,
+                if_false: number("If false:", "throw to monkey")?,
+                inspections: 0,
+            });
+        }
+
+        monkeys.sort_by_key(|monkey| monkey.id);
+
+        // Swap target ids for indexes
+        let ids: Vec<usize> = monkeys.iter().map(|monkey| monkey.id).collect();
+
+        for monkey in monkeys.iter_mut() {
+            for target in [&mut monkey.if_true, &mut monkey.if_false] {
+                if *target == monkey.id {
+                    return Err(MonkeyError::SelfTarget(monkey.id));
+                }
+
+                *target = ids
+                    .binary_search(target)
+                    .map_err(|_| MonkeyError::UnknownTarget {
+                        monkey: monkey.id,
+                        target: *target,
+                    })?;
+            }
+        }
+
+        // Every test still sees the same remainder once worry is reduced by a
+        // common multiple of the divisors. Division and remainder operations
+        // don't survive the reduction, and an overflowing multiple is no use.
+        let modulus = if monkeys.iter().all(|monkey| monkey.operation.is_modular()) {
+            monkeys
+                .iter()
+                .try_fold(1, |acc, monkey| lcm(acc, monkey.div_test))
+        } else {
+            None
+        };
+
+        Ok(Monkies {
+            monkeys,
+            modulus,
+            rounds: 0,
+            trace: None,
+        })
+    }
+
+    // Start recording every throw made by do_round
+    fn enable_trace(&mut self) {
+        self.trace.get_or_insert_with(Vec::new);
+    }
+
+    fn do_round(&mut self, worry_divide: usize) -> Result<(), RoundError> {
+        // Dividing worry doesn't commute with reducing it
+        let modulus = self.modulus.filter(|_| worry_divide == 1);
+
+        self.rounds += 1;
+
+        for i in 0..self.monkeys.len() {
+            while let Some(old_worry) = self.monkeys[i].items.pop_front() {
+                let monkey = &self.monkeys[i];
+
+                let inspect_worry =
+                    monkey
+                        .operation
+                        .eval(old_worry, modulus)
+                        .ok_or(RoundError::WorryOverflow {
+                            monkey: monkey.id,
+                            worry: old_worry,
+                        })?;
 
                 let bored_worry = inspect_worry / worry_divide;
 
                 // Modular arithmetic yo!
-                let simplified_worry = bored_worry % self.gcd;
-
-                let throw_idx = match simplified_worry % self.div_tests[i] {
-                    0 => self.true_indexes[i],
-                    _ => self.false_indexes[i],
+                let simplified_worry = match modulus {
+                    Some(m) => bored_worry % m,
+                    None => bored_worry,
                 };
 
-                self.businesses[i] += 1;
-                self.items[throw_idx].push_back(simplified_worry);
+                let throw_idx = monkey.throw_target(simplified_worry);
+
+                if let Some(trace) = self.trace.as_mut() {
+                    trace.push(Throw {
+                        round: self.rounds,
+                        monkey: monkey.id,
+                        worry_before: old_worry,
+                        worry_after: simplified_worry,
+                        target: self.monkeys[throw_idx].id,
+                    });
+                }
+
+                self.monkeys[i].inspections += 1;
+                self.monkeys[throw_idx].items.push_back(simplified_worry);
             }
         }
+
+        Ok(())
     }
 
-    fn business(&self) -> usize {
-        let mut b = self.businesses.clone();
-        b.sort();
-        b.into_iter().rev().take(2).product::<usize>()
+    fn inspections(&self) -> Vec<usize> {
+        self.monkeys
+            .iter()
+            .map(|monkey| monkey.inspections)
+            .collect()
+    }
+
+    fn business(&self) -> u128 {
+        monkey_business(&self.inspections())
+    }
+
+    // Inspection counts per monkey after `rounds` more rounds without worry
+    // being divided, leaving the monkeys untouched. Items move independently
+    // of each other, and with worry reduced each one only has so many
+    // (monkey, worry) states to be in at the start of a round, so its path
+    // soon repeats and the rest can be extrapolated.
+    fn fast_forward(&self, rounds: usize) -> Result<Vec<usize>, RoundError> {
+        let modulus = self.modulus.ok_or(RoundError::UnboundedWorry)?;
+
+        let mut inspections = self.inspections();
+
+        for (i, monkey) in self.monkeys.iter().enumerate() {
+            for &worry in &monkey.items {
+                let counts = self.item_inspections((i, worry % modulus), rounds, modulus)?;
+
+                for (total, count) in inspections.iter_mut().zip(counts) {
+                    *total += count;
+                }
+            }
+        }
+
+        Ok(inspections)
+    }
+
+    fn item_inspections(
+        &self,
+        start: (usize, usize),
+        rounds: usize,
+        modulus: usize,
+    ) -> Result<Vec<usize>, RoundError> {
+        // Round each state was first seen at the start of, and the counts
+        // inspected before each round
+        let mut seen: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut history: Vec<Vec<usize>> = vec![vec![0; self.monkeys.len()]];
+
+        let mut state = start;
+
+        for round in 0..rounds {
+            if let Some(&cycle_start) = seen.get(&state) {
+                let cycle_len = round - cycle_start;
+                let (full_cycles, rest) =
+                    ((rounds - round) / cycle_len, (rounds - round) % cycle_len);
+
+                let counts = (0..self.monkeys.len())
+                    .map(|i| {
+                        let per_cycle = history[round][i] - history[cycle_start][i];
+                        let partial = history[cycle_start + rest][i] - history[cycle_start][i];
+                        history[round][i] + full_cycles * per_cycle + partial
+                    })
+                    .collect();
+
+                return Ok(counts);
+            }
+
+            seen.insert(state, round);
+
+            let mut counts = history[round].clone();
+            state = self.item_round(state, modulus, &mut counts)?;
+            history.push(counts);
+        }
+
+        Ok(history.pop().unwrap())
+    }
+
+    // Follows one item through a round, which only ends when it's thrown to
+    // a monkey that has already had its turn
+    fn item_round(
+        &self,
+        (mut i, mut worry): (usize, usize),
+        modulus: usize,
+        counts: &mut [usize],
+    ) -> Result<(usize, usize), RoundError> {
+        loop {
+            let monkey = &self.monkeys[i];
+            counts[i] += 1;
+
+            worry =
+                monkey
+                    .operation
+                    .eval(worry, Some(modulus))
+                    .ok_or(RoundError::WorryOverflow {
+                        monkey: monkey.id,
+                        worry,
+                    })?
+                    % modulus;
+
+            let throw_idx = monkey.throw_target(worry);
+
+            if throw_idx <= i {
+                return Ok((throw_idx, worry));
+            }
+
+            i = throw_idx;
+        }
+    }
+}
+
+fn trace_csv(trace: &[Throw]) -> String {
+    let mut out = String::from("round,monkey,worry_before,worry_after,target\n");
+
+    for throw in trace {
+        out.push_str(&format!(
+            "{},{},{},{},{}\n",
+            throw.round, throw.monkey, throw.worry_before, throw.worry_after, throw.target
+        ));
+    }
+
+    out
+}
+
+// Counts of the worry levels each monkey inspected by id, bucketed by powers
+// of two so bucket n holds worry in [2^(n-1), 2^n) and bucket 0 holds 0
+fn worry_histograms(trace: &[Throw], ids: &[usize]) -> BTreeMap<usize, BTreeMap<u32, usize>> {
+    let mut histograms: BTreeMap<usize, BTreeMap<u32, usize>> =
+        ids.iter().map(|&id| (id, BTreeMap::new())).collect();
+
+    for throw in trace {
+        let bucket = usize::BITS - throw.worry_before.leading_zeros();
+        *histograms
+            .entry(throw.monkey)
+            .or_default()
+            .entry(bucket)
+            .or_insert(0) += 1;
+    }
+
+    histograms
+}
+
+fn render_histograms(histograms: &BTreeMap<usize, BTreeMap<u32, usize>>) -> String {
+    let mut out = String::new();
+
+    for (monkey, histogram) in histograms {
+        out.push_str(&format!("Monkey {}:\n", monkey));
+
+        let largest = histogram.values().max().copied().unwrap_or(0);
+
+        for (&bucket, &count) in histogram {
+            let range = match bucket {
+                0 => "0".to_string(),
+                n => format!("{}..{}", 1u128 << (n - 1), 1u128 << n),
+            };
+            let bar = "#".repeat((count * 40).div_ceil(largest));
+
+            out.push_str(&format!("  {:>24} {:>8} {}\n", range, count, bar));
+        }
+    }
+
+    out
+}
+
+// Product of the two highest inspection counts
+fn monkey_business(businesses: &[usize]) -> u128 {
+    let mut b = businesses.to_vec();
+    b.sort();
+    b.into_iter()
+        .rev()
+        .take(2)
+        .map(|count| count as u128)
+        .product()
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
+
+// Divisors are never zero, so neither is the gcd
+fn lcm(a: usize, b: usize) -> Option<usize> {
+    (a / gcd(a, b)).checked_mul(b)
+}
+
+// Runs a part's rounds with every throw traced
+fn traced_rounds(input: &str, part: Option<&str>) -> Result<Monkies, String> {
+    let (rounds, worry_divide) = match part {
+        None | Some("1") => (20, 3),
+        Some("2") => (10_000, 1),
+        Some(part) => return Err(format!("Unknown part {:?}, expected 1 or 2", part)),
+    };
+
+    let mut monkies = Monkies::parse(input).map_err(|err| format!("{:?}", err))?;
+    monkies.enable_trace();
+
+    for _ in 0..rounds {
+        monkies
+            .do_round(worry_divide)
+            .map_err(|err| format!("{:?}", err))?;
+    }
+
+    Ok(monkies)
+}
+
+pub fn trace_option(input: &str, value: Option<&str>) -> String {
+    match traced_rounds(input, value) {
+        Ok(monkies) => trace_csv(monkies.trace.as_deref().unwrap_or_default()),
+        Err(err) => err,
+    }
+}
+
+pub fn histogram_option(input: &str, value: Option<&str>) -> String {
+    match traced_rounds(input, value) {
+        Ok(monkies) => {
+            let ids: Vec<usize> = monkies.monkeys.iter().map(|monkey| monkey.id).collect();
+            let trace = monkies.trace.as_deref().unwrap_or_default();
+
+            render_histograms(&worry_histograms(trace, &ids))
+        }
+        Err(err) => err,
     }
 }
 
 pub fn day11(input: &str) -> (String, String) {
-    let mut p1_monkies = Monkies::parse(input);
+    let mut p1_monkies = Monkies::parse(input).unwrap();
     for _ in 0..20 {
-        p1_monkies.do_round(3);
+        p1_monkies.do_round(3).unwrap();
     }
     let part1 = p1_monkies.business().to_string();
 
-    let mut p2_monkies = Monkies::parse(input);
-    for _ in 0..10_000 {
-        p2_monkies.do_round(1);
-    }
-    let part2 = p2_monkies.business().to_string();
+    let p2_monkies = Monkies::parse(input).unwrap();
+    let part2 = monkey_business(&p2_monkies.fast_forward(10_000).unwrap()).to_string();
 
     (part1, part2)
 }
@@ -188,4 +669,361 @@ mod tests {
         assert_eq!(part1, "10605");
         assert_eq!(part2, "2713310158");
     }
+
+    #[test]
+    fn test_day11_expr_parse() {
+        use super::Expr::{self, BinOp, Num, Old};
+        use super::ExprError::{UnexpectedEnd, UnexpectedToken, UnknownToken};
+
+        let expr = Expr::parse("old * old + 3").unwrap();
+        let expected = BinOp(
+            Box::new(BinOp(Box::new(Old), '*', Box::new(Old))),
+            '+',
+            Box::new(Num(3)),
+        );
+        assert_eq!(expr, expected);
+
+        let expr = Expr::parse("(old - 1) % 7").unwrap();
+        let expected = BinOp(
+            Box::new(BinOp(Box::new(Old), '-', Box::new(Num(1)))),
+            '%',
+            Box::new(Num(7)),
+        );
+        assert_eq!(expr, expected);
+
+        assert_eq!(Expr::parse("old ^ 2"), Err(UnknownToken("^".to_string())));
+        assert_eq!(Expr::parse("new + 2"), Err(UnknownToken("new".to_string())));
+        assert_eq!(Expr::parse("(old + 2"), Err(UnexpectedEnd));
+        assert_eq!(
+            Expr::parse("old 2"),
+            Err(UnexpectedToken("Num(2)".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_day11_expr_eval() {
+        use super::Expr;
+
+        let expr = Expr::parse("old * old + 3").unwrap();
+        assert_eq!(expr.eval(5, None), Some(28));
+
+        let expr = Expr::parse("(old + 10) / 4 - old % 3").unwrap();
+        assert_eq!(expr.eval(7, None), Some(3));
+        assert!(!expr.is_modular());
+
+        let expr = Expr::parse("old - 5").unwrap();
+        assert_eq!(expr.eval(3, None), None);
+        assert_eq!(expr.eval(3, Some(7)), Some(5));
+
+        let expr = Expr::parse("old / (old - old)").unwrap();
+        assert_eq!(expr.eval(3, None), None);
+
+        // A cube overflows unless every step is reduced
+        let expr = Expr::parse("old * old * old").unwrap();
+        assert_eq!(expr.eval(9_699_689, None), None);
+        assert_eq!(expr.eval(9_699_689, Some(9_699_690)), Some(9_699_689));
+        assert_eq!(expr.eval(123_456_789, Some(9_699_690)), Some(3_951_039));
+
+        let large = 1 << 62;
+        assert_eq!(expr.eval(large - 1, Some(large)), Some(large - 1));
+        assert_eq!(expr.eval(large + 3, Some(large)), Some(27));
+
+        let expr = Expr::parse("old * old + old * old - 7").unwrap();
+        assert_eq!(expr.eval(large - 1, Some(large)), Some(large - 5));
+    }
+
+    #[test]
+    fn test_day11_cubed_worry() {
+        use super::Monkies;
+
+        // Part 1 can't reduce worry, so only part 2 survives a cube
+        let input = INPUT_LINES
+            .join("\n")
+            .replace("new = old * old", "new = old * old * old");
+        let monkies = Monkies::parse(&input).unwrap();
+
+        let mut brute = Monkies::parse(&input).unwrap();
+        for _ in 0..1000 {
+            brute.do_round(1).unwrap();
+        }
+
+        assert_eq!(monkies.fast_forward(1000).unwrap(), brute.inspections());
+        assert!(monkies.fast_forward(10_000).is_ok());
+    }
+
+    #[test]
+    fn test_day11_variant_operations() {
+        let input = INPUT_LINES
+            .join("\n")
+            .replace("new = old * 19", "new = (old - 1) * 19 + 19")
+            .replace("new = old * old", "new = old * old + 0 * old");
+        let (part1, part2) = super::day11(&input);
+
+        assert_eq!(part1, "10605");
+        assert_eq!(part2, "2713310158");
+    }
+
+    const NON_PRIME_LINES: [&str; 20] = [
+        "Monkey 0:",
+        "  Starting items: 3, 8, 14",
+        "  Operation: new = old * 3",
+        "  Test: divisible by 4",
+        "    If true: throw to monkey 1",
+        "    If false: throw to monkey 2",
+        "",
+        "Monkey 1:",
+        "  Starting items: 5",
+        "  Operation: new = old + 7",
+        "  Test: divisible by 6",
+        "    If true: throw to monkey 2",
+        "    If false: throw to monkey 0",
+        "",
+        "Monkey 2:",
+        "  Starting items: 9, 10",
+        "  Operation: new = old + 4",
+        "  Test: divisible by 10",
+        "    If true: throw to monkey 0",
+        "    If false: throw to monkey 1",
+    ];
+
+    #[test]
+    fn test_day11_lcm_modulus() {
+        use super::{lcm, Monkies};
+
+        assert_eq!(lcm(4, 6), Some(12));
+        assert_eq!(lcm(12, 10), Some(60));
+        assert_eq!(lcm(usize::MAX, usize::MAX - 1), None);
+
+        let input = NON_PRIME_LINES.join("\n");
+        let mut reduced = Monkies::parse(&input).unwrap();
+        assert_eq!(reduced.modulus, Some(60));
+
+        // Few enough rounds that the unreduced worry levels stay in range
+        let mut unreduced = Monkies::parse(&input).unwrap();
+        unreduced.modulus = None;
+
+        for _ in 0..30 {
+            reduced.do_round(1).unwrap();
+            unreduced.do_round(1).unwrap();
+        }
+
+        assert_eq!(reduced.inspections(), unreduced.inspections());
+        assert!(reduced
+            .monkeys
+            .iter()
+            .flat_map(|monkey| &monkey.items)
+            .all(|&worry| worry < 60));
+    }
+
+    #[test]
+    fn test_day11_worry_overflow() {
+        use super::{Monkies, RoundError};
+
+        // Division stops any reduction, so squaring soon overflows
+        let input = NON_PRIME_LINES
+            .join("\n")
+            .replace("new = old * 3", "new = old * old / 1");
+        let mut monkies = Monkies::parse(&input).unwrap();
+        assert_eq!(monkies.modulus, None);
+
+        let err = (0..100)
+            .map(|_| monkies.do_round(1))
+            .find_map(Result::err)
+            .unwrap();
+        assert!(matches!(err, RoundError::WorryOverflow { monkey: 0, .. }));
+    }
+
+    #[test]
+    fn test_day11_fast_forward() {
+        use super::{Monkies, RoundError};
+        use std::collections::{HashMap, VecDeque};
+
+        for input in [INPUT_LINES.join("\n"), NON_PRIME_LINES.join("\n")] {
+            let mut monkies = Monkies::parse(&input).unwrap();
+
+            for rounds in [1, 20, 1000, 5000] {
+                let mut brute = Monkies::parse(&input).unwrap();
+                for _ in 0..rounds {
+                    brute.do_round(1).unwrap();
+                }
+
+                assert_eq!(monkies.fast_forward(rounds).unwrap(), brute.inspections());
+            }
+
+            // Starting part way through still lines up
+            monkies.do_round(1).unwrap();
+            let mut brute = Monkies::parse(&input).unwrap();
+            for _ in 0..1001 {
+                brute.do_round(1).unwrap();
+            }
+            assert_eq!(monkies.fast_forward(1000).unwrap(), brute.inspections());
+        }
+
+        // Round by round, every item's state comes back after some period P,
+        // so from then on each P rounds add the same inspections
+        let input = INPUT_LINES.join("\n");
+        let mut brute = Monkies::parse(&input).unwrap();
+        let state = |monkies: &Monkies| -> Vec<VecDeque<usize>> {
+            monkies
+                .monkeys
+                .iter()
+                .map(|monkey| monkey.items.clone())
+                .collect()
+        };
+
+        let mut seen: HashMap<Vec<VecDeque<usize>>, (usize, Vec<usize>)> = HashMap::new();
+        let (start, period, start_counts, per_period) = loop {
+            let round = brute.rounds;
+            let counts = brute.inspections();
+
+            if let Some((start, start_counts)) = seen.get(&state(&brute)) {
+                let per_period: Vec<usize> = counts
+                    .iter()
+                    .zip(start_counts)
+                    .map(|(count, start_count)| count - start_count)
+                    .collect();
+                break (*start, round - start, start_counts.clone(), per_period);
+            }
+
+            seen.insert(state(&brute), (round, counts));
+            brute.do_round(1).unwrap();
+        };
+
+        let rounds: usize = 1_000_000_000_000;
+        let (periods, rest) = ((rounds - start) / period, (rounds - start) % period);
+
+        let mut partial = Monkies::parse(&input).unwrap();
+        for _ in 0..start + rest {
+            partial.do_round(1).unwrap();
+        }
+        let expected: Vec<usize> = partial
+            .inspections()
+            .iter()
+            .zip(&start_counts)
+            .zip(&per_period)
+            .map(|((count, start_count), per_period)| {
+                start_count + periods * per_period + (count - start_count)
+            })
+            .collect();
+
+        let monkies = Monkies::parse(&input).unwrap();
+        assert_eq!(monkies.fast_forward(rounds).unwrap(), expected);
+
+        let input = INPUT_LINES
+            .join("\n")
+            .replace("new = old + 3", "new = old / 2 + 3");
+        let monkies = Monkies::parse(&input).unwrap();
+        assert_eq!(monkies.fast_forward(10), Err(RoundError::UnboundedWorry));
+    }
+
+    #[test]
+    fn test_day11_trace() {
+        use super::{trace_csv, worry_histograms, Monkies, Throw};
+
+        let mut monkies = Monkies::parse(&INPUT_LINES.join("\n")).unwrap();
+        monkies.do_round(3).unwrap();
+        assert_eq!(monkies.trace, None);
+
+        monkies.enable_trace();
+        monkies.do_round(3).unwrap();
+
+        let trace = monkies.trace.as_deref().unwrap();
+        assert_eq!(trace.len(), 16);
+
+        // Round 2 starts with monkey 0 holding 20, 23, 27, 26
+        let first = Throw {
+            round: 2,
+            monkey: 0,
+            worry_before: 20,
+            worry_after: 126,
+            target: 3,
+        };
+        assert_eq!(trace[0], first);
+
+        let csv = trace_csv(trace);
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("round,monkey,worry_before,worry_after,target")
+        );
+        assert_eq!(lines.next(), Some("2,0,20,126,3"));
+        assert_eq!(lines.count(), 15);
+
+        let histograms = worry_histograms(trace, &[0, 1, 2, 3]);
+        assert_eq!(histograms[&0].values().sum::<usize>(), 4);
+        assert_eq!(histograms[&0][&5], 4);
+        let total: usize = histograms.values().flat_map(|h| h.values()).sum();
+        assert_eq!(total, trace.len());
+    }
+
+    #[test]
+    fn test_day11_parse_by_label() {
+        use super::Monkies;
+
+        // Reordered fields, an extra line and ids 0, 5, 9 and 12
+        let input = INPUT_LINES
+            .join("\n")
+            .replace("Monkey 1:", "Monkey 5:")
+            .replace("Monkey 2:", "Monkey 9:")
+            .replace("Monkey 3:", "Monkey 12:")
+            .replace("monkey 1", "monkey 5")
+            .replace("monkey 2", "monkey 9")
+            .replace("monkey 3", "monkey 12")
+            .replace(
+                "  Starting items: 79, 98\n  Operation: new = old * 19",
+                "  Operation: new = old * 19\n  Mood: grumpy\n  Starting items: 79, 98",
+            );
+
+        let monkies = Monkies::parse(&input).unwrap();
+        let ids: Vec<usize> = monkies.monkeys.iter().map(|monkey| monkey.id).collect();
+        assert_eq!(ids, vec![0, 5, 9, 12]);
+        assert_eq!(monkies.monkeys[0].items, vec![79, 98]);
+        assert_eq!(
+            (monkies.monkeys[0].if_true, monkies.monkeys[0].if_false),
+            (2, 3)
+        );
+
+        let (part1, part2) = super::day11(&input);
+        assert_eq!(part1, "10605");
+        assert_eq!(part2, "2713310158");
+    }
+
+    #[test]
+    fn test_day11_parse_errors() {
+        use super::MonkeyError::{
+            DuplicateMonkey, MissingField, SelfTarget, UnknownTarget, ZeroDivisor,
+        };
+        use super::Monkies;
+
+        let input = INPUT_LINES.join("\n");
+
+        let err = Monkies::parse(&input.replace("throw to monkey 3", "throw to monkey 7"));
+        assert_eq!(
+            err.unwrap_err(),
+            UnknownTarget {
+                monkey: 0,
+                target: 7
+            }
+        );
+
+        let err = Monkies::parse(&input.replace("Monkey 3:", "Monkey 2:"));
+        assert_eq!(err.unwrap_err(), DuplicateMonkey(2));
+
+        let err = Monkies::parse(&input.replace("Test: divisible by 19", ""));
+        assert_eq!(
+            err.unwrap_err(),
+            MissingField {
+                monkey: 1,
+                field: "Test:"
+            }
+        );
+
+        let err = Monkies::parse(
+            &input.replace("If true: throw to monkey 1", "If true: throw to monkey 2"),
+        );
+        assert_eq!(err.unwrap_err(), SelfTarget(2));
+
+        let err = Monkies::parse(&input.replace("divisible by 13", "divisible by 0"));
+        assert_eq!(err.unwrap_err(), ZeroDivisor(2));
+    }
 }
@@ -1,139 +1,309 @@
-use std::collections::{HashMap, HashSet, VecDeque};
+use super::render;
+use super::search;
+use std::collections::{HashMap, HashSet};
+use std::fs;
 
 const OFFSETS: [(isize, isize); 4] = [(0, 1), (0, -1), (1, 0), (-1, 0)];
 
+#[derive(Debug, PartialEq)]
+enum HeightMapError {
+    InvalidCell(char, (isize, isize)),
+    MissingStart,
+    MissingGoal,
+    DuplicateStart((isize, isize)),
+    DuplicateGoal((isize, isize)),
+}
+
 #[derive(Debug)]
 struct HeightMap {
-    heights: HashMap<(isize, isize), char>,
+    // Elevation from 0 for 'a' up to 25 for 'z'
+    heights: HashMap<(isize, isize), u8>,
     start: (isize, isize),
     goal: (isize, isize),
 }
 
 impl HeightMap {
-    fn parse(input: &str) -> HeightMap {
-        let mut heights: HashMap<(isize, isize), char> = input
-            .lines()
-            .enumerate()
-            .flat_map(|(y, line)| {
-                line.chars()
-                    .enumerate()
-                    .map(move |(x, ch)| ((x as isize, y as isize), ch))
-            })
-            .collect();
-
-        let mut start: (isize, isize) = (0, 0);
-        let mut goal: (isize, isize) = (0, 0);
-
-        for (k, v) in heights.iter() {
-            match v {
-                'S' => start = *k,
-                'E' => goal = *k,
-                _ => (),
+    fn parse(input: &str) -> Result<HeightMap, HeightMapError> {
+        let mut heights: HashMap<(isize, isize), u8> = HashMap::new();
+        let mut start: Option<(isize, isize)> = None;
+        let mut goal: Option<(isize, isize)> = None;
+
+        for (y, line) in input.lines().enumerate() {
+            for (x, ch) in line.trim().chars().enumerate() {
+                let coords = (x as isize, y as isize);
+
+                // S and E sit at the lowest and highest elevations
+                let height = match ch {
+                    'S' if start.is_some() => return Err(HeightMapError::DuplicateStart(coords)),
+                    'E' if goal.is_some() => return Err(HeightMapError::DuplicateGoal(coords)),
+                    'S' => {
+                        start = Some(coords);
+                        'a'
+                    }
+                    'E' => {
+                        goal = Some(coords);
+                        'z'
+                    }
+                    'a'..='z' => ch,
+                    _ => return Err(HeightMapError::InvalidCell(ch, coords)),
+                };
+
+                heights.insert(coords, height as u8 - b'a');
             }
         }
 
-        heights.entry(start).and_modify(|ch| *ch = 'a');
-        heights.entry(goal).and_modify(|ch| *ch = 'z');
-
-        HeightMap {
+        Ok(HeightMap {
             heights,
-            start,
-            goal,
-        }
+            start: start.ok_or(HeightMapError::MissingStart)?,
+            goal: goal.ok_or(HeightMapError::MissingGoal)?,
+        })
     }
 
-    // Bad Djikstra's :)
-    fn find_distance_to_end(&self) -> Option<isize> {
-        let mut open: VecDeque<((isize, isize), isize)> = VecDeque::new();
+    // Neighbouring coords that `can_step(from_height, to_height)` allows
+    fn neighbours(
+        &self,
+        coords: &(isize, isize),
+        can_step: fn(u8, u8) -> bool,
+    ) -> Vec<(isize, isize)> {
+        let current_height = self.heights[coords];
+
+        OFFSETS
+            .map(|offset| (offset.0 + coords.0, offset.1 + coords.1))
+            .into_iter()
+            // Remove out of bounds coords
+            .filter(|neighbour| {
+                self.heights
+                    .get(neighbour)
+                    .is_some_and(|&n_height| can_step(current_height, n_height))
+            })
+            .collect()
+    }
 
-        open.push_back((self.start, 0));
+    // Every step costs 1, so the Manhattan distance never overestimates
+    fn find_path_to_end(&self) -> Option<Vec<(isize, isize)>> {
+        search::astar(
+            [self.start],
+            |coords| self.neighbours(coords, |from, to| to <= from + 1),
+            |_, _| 1,
+            |coords| *coords == self.goal,
+            |&(x, y)| x.abs_diff(self.goal.0) + y.abs_diff(self.goal.1),
+        )
+        .map(|found| found.path)
+    }
 
-        let mut closed: HashSet<(isize, isize)> = HashSet::new();
+    // Searches down from the goal, so the path is returned from the 'a' up
+    fn find_path_from_first_a(&self) -> Option<Vec<(isize, isize)>> {
+        search::bfs(
+            [self.goal],
+            |coords| self.neighbours(coords, |from, to| from <= to + 1),
+            |coords| self.heights[coords] == 0,
+        )
+        .map(|found| found.path.into_iter().rev().collect())
+    }
 
-        while let Some((current_coords, current_dist)) = open.pop_front() {
-            // Don't reconsider coords seen
-            if closed.contains(&current_coords) {
-                continue;
-            }
+    // Steps from the nearest source to every cell reachable by climbing up
+    fn distances_up(&self, sources: &[(isize, isize)]) -> HashMap<(isize, isize), usize> {
+        search::distances(sources.iter().copied(), |coords| {
+            self.neighbours(coords, |from, to| to <= from + 1)
+        })
+    }
 
-            if current_coords == self.goal {
-                return Some(current_dist);
-            }
+    // Steps from every cell that can climb up to one of the targets, to the
+    // nearest of them
+    fn distances_down(&self, targets: &[(isize, isize)]) -> HashMap<(isize, isize), usize> {
+        search::distances(targets.iter().copied(), |coords| {
+            self.neighbours(coords, |from, to| from <= to + 1)
+        })
+    }
 
-            closed.insert(current_coords);
+    // Cells at the lowest elevation that can reach the goal in at most `k` steps,
+    // nearest first
+    fn starts_within(&self, k: usize) -> Vec<((isize, isize), usize)> {
+        let mut starts: Vec<((isize, isize), usize)> = self
+            .distances_down(&[self.goal])
+            .into_iter()
+            .filter(|(coords, dist)| self.heights[coords] == 0 && *dist <= k)
+            .collect();
 
-            let neighbour_coords: Vec<(isize, isize)> = OFFSETS
-                .map(|offset| (offset.0 + current_coords.0, offset.1 + current_coords.1))
-                .into_iter()
-                // Remove out of bounds coords
-                .filter(|neighbour| self.heights.contains_key(neighbour))
-                .collect();
+        starts.sort_by_key(|&((x, y), dist)| (dist, y, x));
+        starts
+    }
 
-            let current_height = *self.heights.get(&current_coords).unwrap() as u8;
+    fn unreachable_from_start(&self) -> Vec<(isize, isize)> {
+        let reachable = self.distances_up(&[self.start]);
 
-            for n in neighbour_coords {
-                let n_height = *self.heights.get(&n).unwrap() as u8;
+        let mut unreachable: Vec<(isize, isize)> = self
+            .heights
+            .keys()
+            .filter(|coords| !reachable.contains_key(coords))
+            .copied()
+            .collect();
 
-                if n_height <= current_height + 1 {
-                    // Abusing properties of square grid and constant distance of 1
-                    open.push_back((n, current_dist + 1));
-                }
-            }
-        }
+        unreachable.sort_by_key(|&(x, y)| (y, x));
+        unreachable
+    }
 
-        None
+    fn size(&self) -> (isize, isize) {
+        let width = self.heights.keys().map(|(x, _)| x + 1).max().unwrap_or(0);
+        let height = self.heights.keys().map(|(_, y)| y + 1).max().unwrap_or(0);
+        (width, height)
     }
 
-    // Bad Djikstra's :)
-    fn find_distance_to_first_a(&self) -> Option<isize> {
-        let mut open: VecDeque<((isize, isize), isize)> = VecDeque::new();
+    // The puzzle's overlay, with each step of the path pointing to the next
+    // and the last marked E
+    fn render_path(&self, path: &[(isize, isize)]) -> String {
+        let (width, height) = self.size();
+
+        let mut arrows: HashMap<(isize, isize), char> = path
+            .windows(2)
+            .map(|step| {
+                let ((x1, y1), (x2, y2)) = (step[0], step[1]);
+                let arrow = match (x2 - x1, y2 - y1) {
+                    (1, 0) => '>',
+                    (-1, 0) => '<',
+                    (0, 1) => 'v',
+                    _ => '^',
+                };
+                (step[0], arrow)
+            })
+            .collect();
 
-        open.push_back((self.goal, 0));
+        if let Some(&end) = path.last() {
+            arrows.insert(end, 'E');
+        }
 
-        let mut closed: HashSet<(isize, isize)> = HashSet::new();
+        (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| *arrows.get(&(x, y)).unwrap_or(&'.'))
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
 
-        while let Some((current_coords, current_dist)) = open.pop_front() {
-            // Don't reconsider coords seen
-            if closed.contains(&current_coords) {
-                continue;
-            }
+    // Binary PPM shading height from dark green up to white, with the path
+    // in red and each cell drawn as a `scale` sized square
+    fn render_path_ppm(&self, path: &[(isize, isize)], scale: usize) -> Vec<u8> {
+        let (width, height) = self.size();
+        let on_path: HashSet<&(isize, isize)> = path.iter().collect();
+
+        let cells: Vec<[u8; 3]> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|coords| {
+                if on_path.contains(&coords) {
+                    [220, 30, 30]
+                } else {
+                    let level = *self.heights.get(&coords).unwrap_or(&0);
+                    let shade = (level as usize * 255 / 25) as u8;
+                    [shade, 80 + (shade as usize * 175 / 255) as u8, shade]
+                }
+            })
+            .collect();
 
-            if self.heights.get(&current_coords).unwrap() == &'a' {
-                return Some(current_dist);
-            }
+        render::ppm(&cells, width as usize, scale)
+    }
+}
 
-            closed.insert(current_coords);
+pub fn day12(input: &str) -> (String, String) {
+    let height_map = HeightMap::parse(input).unwrap();
 
-            let neighbour_coords: Vec<(isize, isize)> = OFFSETS
-                .map(|offset| (offset.0 + current_coords.0, offset.1 + current_coords.1))
-                .into_iter()
-                // Remove out of bounds coords
-                .filter(|neighbour| self.heights.contains_key(neighbour))
-                .collect();
+    let part1 = (height_map.find_path_to_end().unwrap().len() - 1).to_string();
+    let part2 = (height_map.find_path_from_first_a().unwrap().len() - 1).to_string();
 
-            let current_height = *self.heights.get(&current_coords).unwrap() as u8;
+    (part1, part2)
+}
 
-            for n in neighbour_coords {
-                let n_height = *self.heights.get(&n).unwrap() as u8;
+fn find_path(height_map: &HeightMap, part: Option<&str>) -> Result<Vec<(isize, isize)>, String> {
+    let path = match part {
+        None | Some("1") => height_map.find_path_to_end(),
+        Some("2") => height_map.find_path_from_first_a(),
+        Some(part) => return Err(format!("Unknown part {:?}, expected 1 or 2", part)),
+    };
 
-                if current_height <= n_height + 1 {
-                    // Abusing properties of square grid and constant distance of 1
-                    open.push_back((n, current_dist + 1));
-                }
-            }
-        }
+    path.ok_or_else(|| "No path found".to_string())
+}
 
-        None
+pub fn path_option(input: &str, value: Option<&str>) -> String {
+    let height_map = match HeightMap::parse(input) {
+        Ok(height_map) => height_map,
+        Err(err) => return format!("{:?}", err),
+    };
+
+    match find_path(&height_map, value) {
+        Ok(path) => height_map.render_path(&path),
+        Err(err) => err,
     }
 }
 
-pub fn day12(input: &str) -> (String, String) {
-    let height_map = HeightMap::parse(input);
+// Value is the file to write, optionally followed by the part, e.g. path.ppm,2
+pub fn path_ppm_option(input: &str, value: Option<&str>) -> String {
+    let (file, part) = match value.unwrap_or("path.ppm").split_once(',') {
+        Some((file, part)) => (file, Some(part)),
+        None => (value.unwrap_or("path.ppm"), None),
+    };
+
+    let height_map = match HeightMap::parse(input) {
+        Ok(height_map) => height_map,
+        Err(err) => return format!("{:?}", err),
+    };
+
+    let path = match find_path(&height_map, part) {
+        Ok(path) => path,
+        Err(err) => return err,
+    };
+
+    match fs::write(file, height_map.render_path_ppm(&path, 4)) {
+        Ok(_) => format!("Wrote path of {} steps to {}", path.len() - 1, file),
+        Err(err) => format!("Could not write {}: {}", file, err),
+    }
+}
 
-    let part1 = height_map.find_distance_to_end().unwrap().to_string();
-    let part2 = height_map.find_distance_to_first_a().unwrap().to_string();
+// Value is the step limit for listing nearby start cells, defaulting to the
+// part 1 distance
+pub fn report_option(input: &str, value: Option<&str>) -> String {
+    let height_map = match HeightMap::parse(input) {
+        Ok(height_map) => height_map,
+        Err(err) => return format!("{:?}", err),
+    };
+
+    let from_start = height_map.distances_up(&[height_map.start]);
+    let to_goal = height_map.distances_down(&[height_map.goal]);
+    let unreachable = height_map.unreachable_from_start();
+
+    let limit = match value.map(|value| value.parse::<usize>()) {
+        None => to_goal.get(&height_map.start).copied().unwrap_or(0),
+        Some(Ok(limit)) => limit,
+        Some(Err(_)) => return format!("Invalid step limit {:?}", value.unwrap()),
+    };
+
+    let a_cells = height_map.heights.values().filter(|&&h| h == 0).count();
+    let a_to_goal: Vec<usize> = to_goal
+        .iter()
+        .filter(|(coords, _)| height_map.heights[coords] == 0)
+        .map(|(_, dist)| *dist)
+        .collect();
+    let nearby = height_map.starts_within(limit);
+
+    let mut out = String::new();
+    out.push_str(&format!("Cells: {}\n", height_map.heights.len()));
+    out.push_str(&format!("Reachable from S: {}\n", from_start.len()));
+    out.push_str(&format!("Unreachable from S: {}\n", unreachable.len()));
+    out.push_str(&format!("Can reach E: {}\n", to_goal.len()));
+    out.push_str(&format!(
+        "Cells at a that can reach E: {} of {}, {} to {} steps\n",
+        a_to_goal.len(),
+        a_cells,
+        a_to_goal.iter().min().unwrap_or(&0),
+        a_to_goal.iter().max().unwrap_or(&0),
+    ));
+    out.push_str(&format!("Cells at a within {} steps of E:", limit));
+
+    for ((x, y), dist) in nearby {
+        out.push_str(&format!("\n    ({}, {}): {}", x, y, dist));
+    }
 
-    (part1, part2)
+    out
 }
 
 #[cfg(test)]
@@ -141,11 +311,129 @@ mod tests {
     const INPUT_LINES: [&str; 5] = ["Sabqponm", "abcryxxl", "accszExk", "acctuvwj", "abdefghi"];
 
     #[test]
-    fn test_day0() {
+    fn test_day12() {
         let input = INPUT_LINES.join("\n");
         let (part1, part2) = super::day12(&input);
 
         assert_eq!(part1, "31");
         assert_eq!(part2, "29");
     }
+
+    #[test]
+    fn test_day12_parse() {
+        use super::HeightMap;
+        use super::HeightMapError::{
+            DuplicateGoal, DuplicateStart, InvalidCell, MissingGoal, MissingStart,
+        };
+
+        let height_map = HeightMap::parse("SbE\nazy").unwrap();
+        assert_eq!(height_map.start, (0, 0));
+        assert_eq!(height_map.goal, (2, 0));
+        assert_eq!(height_map.heights[&(0, 0)], 0);
+        assert_eq!(height_map.heights[&(1, 0)], 1);
+        assert_eq!(height_map.heights[&(2, 0)], 25);
+        assert_eq!(height_map.heights[&(1, 1)], 25);
+
+        assert_eq!(HeightMap::parse("abE").unwrap_err(), MissingStart);
+        assert_eq!(HeightMap::parse("Sbc").unwrap_err(), MissingGoal);
+        assert_eq!(
+            HeightMap::parse("SbE\nSzz").unwrap_err(),
+            DuplicateStart((0, 1))
+        );
+        assert_eq!(
+            HeightMap::parse("SbE\nEzz").unwrap_err(),
+            DuplicateGoal((0, 1))
+        );
+        assert_eq!(
+            HeightMap::parse("Sb?E").unwrap_err(),
+            InvalidCell('?', (2, 0))
+        );
+    }
+
+    #[test]
+    fn test_day12_path() {
+        use super::HeightMap;
+
+        let height_map = HeightMap::parse(&INPUT_LINES.join("\n")).unwrap();
+
+        let path = height_map.find_path_to_end().unwrap();
+        assert_eq!(path.len(), 32);
+        assert_eq!(path.first(), Some(&height_map.start));
+        assert_eq!(path.last(), Some(&height_map.goal));
+        assert!(path
+            .windows(2)
+            .all(|step| step[0].0.abs_diff(step[1].0) + step[0].1.abs_diff(step[1].1) == 1));
+
+        // Climbing from S to E never steps up more than one elevation
+        assert!(path
+            .windows(2)
+            .all(|step| height_map.heights[&step[1]] <= height_map.heights[&step[0]] + 1));
+
+        let rendered = height_map.render_path(&path);
+        assert_eq!(rendered.lines().count(), 5);
+        assert_eq!(rendered.matches('E').count(), 1);
+        assert_eq!(
+            rendered.chars().filter(|ch| "<>^v".contains(*ch)).count(),
+            31
+        );
+
+        let path = height_map.find_path_from_first_a().unwrap();
+        assert_eq!(path.len(), 30);
+        assert_eq!(height_map.heights[path.first().unwrap()], 0);
+        assert_eq!(path.last(), Some(&height_map.goal));
+    }
+
+    #[test]
+    fn test_day12_render_path() {
+        use super::HeightMap;
+
+        let height_map = HeightMap::parse("Sbc\nfed\nghE").unwrap();
+        let path = vec![
+            (0, 0),
+            (1, 0),
+            (2, 0),
+            (2, 1),
+            (1, 1),
+            (0, 1),
+            (0, 2),
+            (1, 2),
+            (2, 2),
+        ];
+
+        assert_eq!(height_map.render_path(&path), ">>v\nv<<\n>>E");
+
+        let ppm = height_map.render_path_ppm(&path, 2);
+        assert!(ppm.starts_with(b"P6\n6 6\n255\n"));
+        assert_eq!(ppm.len(), "P6\n6 6\n255\n".len() + 6 * 6 * 3);
+    }
+
+    #[test]
+    fn test_day12_distance_fields() {
+        use super::HeightMap;
+
+        let height_map = HeightMap::parse(&INPUT_LINES.join("\n")).unwrap();
+
+        let from_start = height_map.distances_up(&[height_map.start]);
+        assert_eq!(from_start[&height_map.goal], 31);
+        assert_eq!(from_start.len(), 40);
+
+        // Every cell on the left edge is an 'a' or 'S'
+        let left_edge: Vec<(isize, isize)> = (0..5).map(|y| (0, y)).collect();
+        let from_left = height_map.distances_up(&left_edge);
+        assert_eq!(from_left[&height_map.goal], 29);
+
+        let nearby = height_map.starts_within(30);
+        assert_eq!(nearby[0], ((0, 4), 29));
+        assert_eq!(&nearby[1..], &[((1, 0), 30), ((0, 1), 30), ((0, 3), 30)]);
+        assert!(height_map.starts_within(28).is_empty());
+
+        assert!(height_map.unreachable_from_start().is_empty());
+
+        // Walled off by a cliff, only the 'b' to the left is reachable
+        let height_map = HeightMap::parse("Sbz\nzzE").unwrap();
+        assert_eq!(
+            height_map.unreachable_from_start(),
+            vec![(2, 0), (0, 1), (1, 1), (2, 1)]
+        );
+    }
 }
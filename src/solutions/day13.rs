@@ -1,12 +1,18 @@
 use std::cmp::Ordering;
-use std::fmt::{Debug, Formatter, Result};
+use std::fmt::{Debug, Display, Formatter, Result};
+use std::fs;
+use std::str::FromStr;
 
+// The original string-splitting parser's helpers, kept so the byte-level
+// parser can be benchmarked against it
+#[cfg(test)]
 trait StringExt {
     fn is_bracketed(&self) -> bool;
     fn strip_brackets(self) -> String;
     fn chunk_outer(self) -> Vec<String>;
 }
 
+#[cfg(test)]
 impl StringExt for String {
     fn is_bracketed(&self) -> bool {
         if self.len() < 2 {
@@ -45,11 +51,7 @@ impl StringExt for String {
             match ch {
                 '[' => depth += 1,
                 ']' => depth -= 1,
-                ',' => {
-                    if depth == 0 {
-                        comma_idxs.push(i);
-                    }
-                }
+                ',' if depth == 0 => comma_idxs.push(i),
                 _ => (),
             }
         }
@@ -69,26 +71,39 @@ impl StringExt for String {
     }
 }
 
+#[derive(Debug, PartialEq)]
+enum PacketError {
+    UnexpectedChar { pos: usize, found: char },
+    UnexpectedEnd { pos: usize },
+    TrailingInput { pos: usize },
+    NumberOverflow { pos: usize },
+}
+
 #[derive(Clone, PartialEq, Eq)]
 enum Packet {
     Num(usize),
     List(Vec<Packet>),
 }
 
+// Written compactly in the same form as the puzzle input, or indented with
+// `{:#}`. Lists holding only numbers stay on one line when indented.
+impl Display for Packet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        self.write(f, f.alternate().then_some(0))
+    }
+}
+
 impl Debug for Packet {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        match self {
-            Self::Num(num) => write!(f, "{}", num),
-            Self::List(list) => {
-                let formatted = list
-                    .iter()
-                    .map(|packet| format!("{:?}", packet))
-                    .collect::<Vec<_>>()
-                    .join(",");
-
-                write!(f, "[{}]", formatted)
-            }
-        }
+        Display::fmt(self, f)
+    }
+}
+
+impl FromStr for Packet {
+    type Err = PacketError;
+
+    fn from_str(input: &str) -> std::result::Result<Packet, PacketError> {
+        Packet::parse(input)
     }
 }
 
@@ -103,44 +118,411 @@ impl Ord for Packet {
         use Packet::{List, Num};
 
         match (self, other) {
-            // Wrap number as list
-            (lhs_num @ Num(_), rhs_list @ List(_)) => {
-                let lhs_list = List(vec![lhs_num.clone()]);
-                lhs_list.cmp(rhs_list)
-            }
-
-            // Wrap number as list
-            (lhs_list @ List(_), rhs_num @ Num(_)) => {
-                let rhs_list = List(vec![rhs_num.clone()]);
-                lhs_list.cmp(&rhs_list)
-            }
+            // Treat number as a list holding just itself
+            (Num(_), List(rhs_list)) => cmp_lists(std::slice::from_ref(self), rhs_list),
+            (List(lhs_list), Num(_)) => cmp_lists(lhs_list, std::slice::from_ref(other)),
 
             // Compare numbers
             (Num(lhs_num), Num(rhs_num)) => lhs_num.cmp(rhs_num),
 
             // Compare lists
-            (List(lhs_list), List(rhs_list)) => {
-                let (lhs_len, rhs_len) = (lhs_list.len(), rhs_list.len());
-                let min_len = lhs_len.min(rhs_len);
+            (List(lhs_list), List(rhs_list)) => cmp_lists(lhs_list, rhs_list),
+        }
+    }
+}
 
-                for i in 0..min_len {
-                    let (l_elem, r_elem) = (&lhs_list[i], &rhs_list[i]);
+fn cmp_lists(lhs_list: &[Packet], rhs_list: &[Packet]) -> Ordering {
+    for (l_elem, r_elem) in lhs_list.iter().zip(rhs_list) {
+        match l_elem.cmp(r_elem) {
+            Ordering::Equal => continue,
+            order => return order,
+        }
+    }
 
-                    match l_elem.cmp(r_elem) {
-                        Ordering::Equal => continue,
-                        order => return order,
+    // One or both lists have run out
+    lhs_list.len().cmp(&rhs_list.len())
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Num(usize),
+}
+
+// Reads tokens straight from packet text. A number compared against a list
+// is wrapped by replaying it followed by a close that isn't in the text.
+struct Tokens<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    wrapped: Option<usize>,
+    closes: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(text: &'a str) -> Tokens<'a> {
+        Tokens {
+            bytes: text.as_bytes(),
+            pos: 0,
+            wrapped: None,
+            closes: 0,
+        }
+    }
+
+    fn wrap(&mut self, num: usize) {
+        self.wrapped = Some(num);
+        self.closes += 1;
+    }
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if let Some(num) = self.wrapped.take() {
+            return Some(Token::Num(num));
+        }
+
+        if self.closes > 0 {
+            self.closes -= 1;
+            return Some(Token::Close);
+        }
+
+        while let Some(&byte) = self.bytes.get(self.pos) {
+            self.pos += 1;
+
+            match byte {
+                b'[' => return Some(Token::Open),
+                b']' => return Some(Token::Close),
+                b'0'..=b'9' => {
+                    let mut num = (byte - b'0') as usize;
+
+                    while let Some(&digit @ b'0'..=b'9') = self.bytes.get(self.pos) {
+                        num = num * 10 + (digit - b'0') as usize;
+                        self.pos += 1;
                     }
+
+                    return Some(Token::Num(num));
                 }
+                _ => (),
+            }
+        }
+
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Side {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum StepKind {
+    Compare(String, String),
+    Convert(Side, String),
+    Smaller(Side),
+    RanOut(Side),
+}
+
+// One line of an explanation. `path` holds the list indexes leading to the
+// values being compared, and `depth` how far the line is indented.
+#[derive(Debug, Clone, PartialEq)]
+struct Step {
+    path: Vec<usize>,
+    depth: usize,
+    kind: StepKind,
+}
+
+#[derive(Debug)]
+struct Explanation {
+    steps: Vec<Step>,
+    ordering: Ordering,
+}
+
+impl Explanation {
+    // The step that settled the ordering, if the packets weren't equal
+    fn deciding_step(&self) -> Option<&Step> {
+        self.steps
+            .last()
+            .filter(|step| matches!(step.kind, StepKind::Smaller(_) | StepKind::RanOut(_)))
+    }
+
+    // In the puzzle's "- Compare x vs y" format
+    fn render(&self) -> String {
+        self.steps
+            .iter()
+            .map(|step| {
+                let text = match &step.kind {
+                    StepKind::Compare(lhs, rhs) => format!("Compare {} vs {}", lhs, rhs),
+                    StepKind::Convert(side, to) => format!(
+                        "Mixed types; convert {} to {} and retry comparison",
+                        side.name(),
+                        to
+                    ),
+                    StepKind::Smaller(Side::Left) => {
+                        "Left side is smaller, so inputs are in the right order".to_string()
+                    }
+                    StepKind::Smaller(Side::Right) => {
+                        "Right side is smaller, so inputs are not in the right order".to_string()
+                    }
+                    StepKind::RanOut(Side::Left) => {
+                        "Left side ran out of items, so inputs are in the right order".to_string()
+                    }
+                    StepKind::RanOut(Side::Right) => {
+                        "Right side ran out of items, so inputs are not in the right order"
+                            .to_string()
+                    }
+                };
+
+                format!("{:width$}- {}", "", text, width = step.depth * 2)
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+impl Side {
+    fn name(&self) -> &'static str {
+        match self {
+            Side::Left => "left",
+            Side::Right => "right",
+        }
+    }
+}
+
+// Orders two packets the same way as `Packet::cmp` without parsing them.
+// Both must be well-formed packets.
+fn cmp_text(lhs: &str, rhs: &str) -> Ordering {
+    use Token::{Close, Num, Open};
+
+    let (mut lhs_tokens, mut rhs_tokens) = (Tokens::new(lhs), Tokens::new(rhs));
 
-                // One or both lists have run out
-                lhs_len.cmp(&rhs_len)
+    loop {
+        match (lhs_tokens.next(), rhs_tokens.next()) {
+            (Some(Num(lhs_num)), Some(Num(rhs_num))) if lhs_num != rhs_num => {
+                return lhs_num.cmp(&rhs_num)
             }
+            (Some(Num(lhs_num)), Some(Open)) => lhs_tokens.wrap(lhs_num),
+            (Some(Open), Some(Num(rhs_num))) => rhs_tokens.wrap(rhs_num),
+
+            // One list has run out first
+            (Some(Close), Some(Open | Num(_))) | (None, Some(_)) => return Ordering::Less,
+            (Some(Open | Num(_)), Some(Close)) | (Some(_), None) => return Ordering::Greater,
+
+            (None, None) => return Ordering::Equal,
+            _ => (),
         }
     }
 }
 
+fn skip_whitespace(bytes: &[u8], mut pos: usize) -> usize {
+    while bytes.get(pos).is_some_and(u8::is_ascii_whitespace) {
+        pos += 1;
+    }
+
+    pos
+}
+
 impl Packet {
-    fn parse(input: String) -> Packet {
+    // Parses in a single pass over the bytes. Open lists are kept on a
+    // stack rather than recursing, so deep nesting can't overflow. Whitespace
+    // between tokens is skipped, as JSON writers often add it.
+    fn parse(input: &str) -> std::result::Result<Packet, PacketError> {
+        use Packet::{List, Num};
+
+        let bytes = input.as_bytes();
+        let mut open: Vec<Vec<Packet>> = Vec::new();
+        let mut pos: usize = skip_whitespace(bytes, 0);
+
+        let unexpected = |pos: usize| match input[pos..].chars().next() {
+            Some(found) => PacketError::UnexpectedChar { pos, found },
+            None => PacketError::UnexpectedEnd { pos },
+        };
+
+        loop {
+            // A value is expected at the start, after '[' and after ','
+            let mut value = match bytes.get(pos) {
+                Some(b'[') => {
+                    pos = skip_whitespace(bytes, pos + 1);
+
+                    if bytes.get(pos) != Some(&b']') {
+                        open.push(Vec::new());
+                        continue;
+                    }
+
+                    pos += 1;
+                    List(Vec::new())
+                }
+                Some(b'0'..=b'9') => {
+                    let start = pos;
+                    let mut num: usize = 0;
+
+                    while let Some(&digit @ b'0'..=b'9') = bytes.get(pos) {
+                        num = num
+                            .checked_mul(10)
+                            .and_then(|num| num.checked_add((digit - b'0') as usize))
+                            .ok_or(PacketError::NumberOverflow { pos: start })?;
+                        pos += 1;
+                    }
+
+                    Num(num)
+                }
+                _ => return Err(unexpected(pos)),
+            };
+
+            // Add the value to the innermost open list, closing any lists
+            // that end straight after it
+            loop {
+                pos = skip_whitespace(bytes, pos);
+
+                let Some(list) = open.last_mut() else {
+                    if pos < bytes.len() {
+                        return Err(PacketError::TrailingInput { pos });
+                    }
+
+                    return Ok(value);
+                };
+
+                list.push(value);
+
+                match bytes.get(pos) {
+                    Some(b',') => {
+                        pos = skip_whitespace(bytes, pos + 1);
+                        break;
+                    }
+                    Some(b']') => {
+                        pos += 1;
+                        value = List(open.pop().unwrap());
+                    }
+                    _ => return Err(unexpected(pos)),
+                }
+            }
+        }
+    }
+
+    // Compares the same way as `cmp`, recording each step along the way
+    fn explain(&self, other: &Packet) -> Explanation {
+        let mut steps: Vec<Step> = Vec::new();
+        let ordering = self.explain_into(other, 0, &mut Vec::new(), &mut steps);
+
+        Explanation { steps, ordering }
+    }
+
+    fn explain_into(
+        &self,
+        other: &Packet,
+        depth: usize,
+        path: &mut Vec<usize>,
+        steps: &mut Vec<Step>,
+    ) -> Ordering {
+        use Packet::{List, Num};
+
+        let mut step = |depth: usize, kind: StepKind| {
+            steps.push(Step {
+                path: path.clone(),
+                depth,
+                kind,
+            })
+        };
+
+        step(
+            depth,
+            StepKind::Compare(self.to_string(), other.to_string()),
+        );
+
+        match (self, other) {
+            (Num(lhs_num), Num(rhs_num)) => {
+                let ordering = lhs_num.cmp(rhs_num);
+
+                match ordering {
+                    Ordering::Less => step(depth + 1, StepKind::Smaller(Side::Left)),
+                    Ordering::Greater => step(depth + 1, StepKind::Smaller(Side::Right)),
+                    Ordering::Equal => (),
+                }
+
+                ordering
+            }
+
+            (Num(_), List(_)) => {
+                let wrapped = List(vec![self.clone()]);
+                step(
+                    depth + 1,
+                    StepKind::Convert(Side::Left, wrapped.to_string()),
+                );
+                wrapped.explain_into(other, depth + 1, path, steps)
+            }
+
+            (List(_), Num(_)) => {
+                let wrapped = List(vec![other.clone()]);
+                step(
+                    depth + 1,
+                    StepKind::Convert(Side::Right, wrapped.to_string()),
+                );
+                self.explain_into(&wrapped, depth + 1, path, steps)
+            }
+
+            (List(lhs_list), List(rhs_list)) => {
+                for (i, (l_elem, r_elem)) in lhs_list.iter().zip(rhs_list).enumerate() {
+                    path.push(i);
+                    let ordering = l_elem.explain_into(r_elem, depth + 1, path, steps);
+                    path.pop();
+
+                    if ordering != Ordering::Equal {
+                        return ordering;
+                    }
+                }
+
+                let ordering = lhs_list.len().cmp(&rhs_list.len());
+                let side = match ordering {
+                    Ordering::Less => Side::Left,
+                    Ordering::Greater => Side::Right,
+                    Ordering::Equal => return ordering,
+                };
+
+                steps.push(Step {
+                    path: path.clone(),
+                    depth: depth + 1,
+                    kind: StepKind::RanOut(side),
+                });
+
+                ordering
+            }
+        }
+    }
+
+    fn write(&self, f: &mut Formatter<'_>, indent: Option<usize>) -> Result {
+        let list = match self {
+            Packet::Num(num) => return write!(f, "{}", num),
+            Packet::List(list) => list,
+        };
+
+        let indent = indent.filter(|_| list.iter().any(|elem| matches!(elem, Packet::List(_))));
+
+        write!(f, "[")?;
+
+        for (i, elem) in list.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+
+            if let Some(indent) = indent {
+                write!(f, "\n{:width$}", "", width = (indent + 1) * 2)?;
+            }
+
+            elem.write(f, indent.map(|indent| indent + 1))?;
+        }
+
+        if let Some(indent) = indent {
+            write!(f, "\n{:width$}", "", width = indent * 2)?;
+        }
+
+        write!(f, "]")
+    }
+
+    #[cfg(test)]
+    fn parse_strings(input: String) -> Packet {
         use Packet::{List, Num};
 
         if !input.is_bracketed() {
@@ -152,20 +534,82 @@ impl Packet {
         let mut out: Vec<Self> = Vec::new();
 
         for chunk in inner.chunk_outer() {
-            out.push(Self::parse(chunk));
+            out.push(Self::parse_strings(chunk));
         }
 
         List(out)
     }
 }
 
-pub fn day13(input: &str) -> (String, String) {
-    let mut packets: Vec<Packet> = input
+// Packets from a JSON array of them, as other tools write. Packets are
+// written the same way as JSON arrays, so this is just the outer list.
+fn packets_from_json(text: &str) -> std::result::Result<Vec<Packet>, PacketError> {
+    match Packet::parse(text)? {
+        Packet::List(packets) => Ok(packets),
+        Packet::Num(_) => {
+            let pos = skip_whitespace(text.as_bytes(), 0);
+            let found = text[pos..].chars().next().unwrap();
+            Err(PacketError::UnexpectedChar { pos, found })
+        }
+    }
+}
+
+fn parse_packets(input: &str) -> Vec<Packet> {
+    input
         .trim()
         .lines()
         .filter(|line| !line.is_empty())
-        .map(|line| Packet::parse(line.to_string()))
-        .collect();
+        .map(|line| line.trim().parse().unwrap())
+        .collect()
+}
+
+const DIVIDERS: [&str; 2] = ["[[2]]", "[[6]]"];
+
+fn packet_lines(input: &str) -> Vec<&str> {
+    input
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+// Where each probe would end up, counting from 1, if it was added to the
+// packets and everything sorted. Probes go ahead of any equal packets, and
+// equal probes keep their given order.
+fn probe_ranks(packets: &[&str], probes: &[&str]) -> Vec<usize> {
+    probes
+        .iter()
+        .enumerate()
+        .map(|(i, probe)| {
+            let packets_before = packets
+                .iter()
+                .filter(|packet| cmp_text(packet, probe) == Ordering::Less)
+                .count();
+
+            let probes_before = probes
+                .iter()
+                .enumerate()
+                .filter(|&(j, other)| match cmp_text(other, probe) {
+                    Ordering::Less => true,
+                    Ordering::Equal => j < i,
+                    Ordering::Greater => false,
+                })
+                .count();
+
+            packets_before + probes_before + 1
+        })
+        .collect()
+}
+
+// The product of the probes' ranks, along with the ranks themselves
+fn decoder_key(packets: &[&str], probes: &[&str]) -> (usize, Vec<usize>) {
+    let ranks = probe_ranks(packets, probes);
+
+    (ranks.iter().product(), ranks)
+}
+
+pub fn day13(input: &str) -> (String, String) {
+    let packets = parse_packets(input);
 
     let part1 = packets
         .chunks_exact(2)
@@ -175,24 +619,109 @@ pub fn day13(input: &str) -> (String, String) {
         .sum::<usize>()
         .to_string();
 
-    // Insert divider packets and sort
-    packets.push(Packet::parse("[[2]]".to_string()));
-    packets.push(Packet::parse("[[6]]".to_string()));
-    packets.sort();
+    let (decoder_key, _) = decoder_key(&packet_lines(input), &DIVIDERS);
+    let part2 = decoder_key.to_string();
+
+    (part1, part2)
+}
+
+// Ranks of the given space separated probe packets, or the dividers
+pub fn probes_option(input: &str, value: Option<&str>) -> String {
+    let probes: Vec<&str> = match value {
+        Some(value) => value.split_whitespace().collect(),
+        None => DIVIDERS.to_vec(),
+    };
+
+    if let Some((probe, err)) = probes
+        .iter()
+        .find_map(|probe| Packet::parse(probe).err().map(|err| (probe, err)))
+    {
+        return format!("Invalid probe {}: {:?}", probe, err);
+    }
 
-    // Construct new dividers to compare to since previous ones were moved
-    let divider1 = Packet::parse("[[2]]".to_string());
-    let divider2 = Packet::parse("[[6]]".to_string());
+    let (key, ranks) = decoder_key(&packet_lines(input), &probes);
 
-    let part2 = packets
+    probes
+        .iter()
+        .zip(ranks)
+        .map(|(probe, rank)| format!("{} ranks {}", probe, rank))
+        .chain([format!("Decoder key: {}", key)])
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// How the nth pair was ordered, or every pair
+pub fn explain_option(input: &str, value: Option<&str>) -> String {
+    let packets = parse_packets(input);
+    let pairs = packets.len() / 2;
+
+    let chosen: Vec<usize> = match value.map(|value| value.parse::<usize>()) {
+        None => (1..=pairs).collect(),
+        Some(Ok(n)) if (1..=pairs).contains(&n) => vec![n],
+        _ => return format!("Expected a pair number from 1 to {}", pairs),
+    };
+
+    chosen
         .into_iter()
-        .enumerate()
-        .filter(|(_, packet)| *packet == divider1 || *packet == divider2)
-        .map(|(i, _)| i + 1)
-        .product::<usize>()
-        .to_string();
+        .map(|n| {
+            let explanation = packets[2 * n - 2].explain(&packets[2 * n - 1]);
+
+            let decided = match (explanation.ordering, explanation.deciding_step()) {
+                (Ordering::Equal, _) | (_, None) => "Packets are equal".to_string(),
+                (Ordering::Less, Some(step)) => format!("In order, decided at {:?}", step.path),
+                (Ordering::Greater, Some(step)) => {
+                    format!("Not in order, decided at {:?}", step.path)
+                }
+            };
 
-    (part1, part2)
+            format!("== Pair {} ==\n{}\n{}", n, explanation.render(), decided)
+        })
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+// Every packet indented, or just the nth one
+pub fn pretty_option(input: &str, value: Option<&str>) -> String {
+    let packets = parse_packets(input);
+
+    match value.map(|value| value.parse::<usize>()) {
+        None => packets
+            .iter()
+            .map(|packet| format!("{:#}", packet))
+            .collect::<Vec<String>>()
+            .join("\n\n"),
+        Some(Ok(n)) if (1..=packets.len()).contains(&n) => format!("{:#}", packets[n - 1]),
+        _ => format!("Expected a packet number from 1 to {}", packets.len()),
+    }
+}
+
+// One JSON object per pair with both packets and whether they're in order.
+// With a file as the value, the packets are read from a JSON array in it
+// instead of the input.
+pub fn json_option(input: &str, value: Option<&str>) -> String {
+    let packets = match value.map(fs::read_to_string) {
+        None => parse_packets(input),
+        Some(Ok(text)) => match packets_from_json(&text) {
+            Ok(packets) => packets,
+            Err(err) => return format!("{:?}", err),
+        },
+        Some(Err(err)) => return format!("Could not read {}: {}", value.unwrap(), err),
+    };
+
+    packets
+        .chunks_exact(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            format!(
+                r#"{{"pair":{},"left":{},"right":{},"ordered":{}}}"#,
+                i + 1,
+                pair[0],
+                pair[1],
+                pair[0] < pair[1]
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
 }
 
 #[cfg(test)]
@@ -239,15 +768,15 @@ mod tests {
         use super::Packet;
         use super::Packet::{List, Num};
 
-        let parsed = Packet::parse("[1,1,3,1,1]".to_string());
+        let parsed = Packet::parse("[1,1,3,1,1]").unwrap();
         let packet = List(vec![Num(1), Num(1), Num(3), Num(1), Num(1)]);
         assert_eq!(parsed, packet);
 
-        let parsed = Packet::parse("[[[]]]".to_string());
+        let parsed = Packet::parse("[[[]]]").unwrap();
         let packet = List(vec![List(vec![List(vec![])])]);
         assert_eq!(parsed, packet);
 
-        let parsed = Packet::parse("[1,[2,[3,[4,[5,6,7]]]],8,9]".to_string());
+        let parsed = Packet::parse("[1,[2,[3,[4,[5,6,7]]]],8,9]").unwrap();
         let packet = List(vec![
             Num(1),
             List(vec![
@@ -263,21 +792,371 @@ mod tests {
         assert_eq!(parsed, packet);
     }
 
+    #[test]
+    fn test_day13_packet_parse_errors() {
+        use super::Packet;
+        use super::PacketError::{NumberOverflow, TrailingInput, UnexpectedChar, UnexpectedEnd};
+
+        let err = Packet::parse("[1,,2]").unwrap_err();
+        assert_eq!(err, UnexpectedChar { pos: 3, found: ',' });
+
+        let err = Packet::parse("[1,[2]").unwrap_err();
+        assert_eq!(err, UnexpectedEnd { pos: 6 });
+
+        let err = Packet::parse("[1 2]").unwrap_err();
+        assert_eq!(err, UnexpectedChar { pos: 3, found: '2' });
+
+        let err = Packet::parse("[1,é]").unwrap_err();
+        assert_eq!(
+            err,
+            UnexpectedChar {
+                pos: 3, found: 'é'
+            }
+        );
+
+        let err = Packet::parse("[1],[2]").unwrap_err();
+        assert_eq!(err, TrailingInput { pos: 3 });
+
+        let err = Packet::parse("[1,99999999999999999999999]").unwrap_err();
+        assert_eq!(err, NumberOverflow { pos: 3 });
+
+        let err = Packet::parse("").unwrap_err();
+        assert_eq!(err, UnexpectedEnd { pos: 0 });
+    }
+
+    // `[1,[2,[3,...[depth]...],3],2],1]`
+    fn nested_packet(depth: usize) -> String {
+        let open: String = (1..=depth).map(|i| format!("[{},", i)).collect();
+        let close: String = (1..=depth).rev().map(|i| format!("],{}", i)).collect();
+
+        format!("[{}{}{}]", open, depth + 1, close)
+    }
+
+    #[test]
+    fn test_day13_packet_parse_deep() {
+        use super::Packet;
+
+        for depth in [0, 1, 5, 50] {
+            let input = nested_packet(depth);
+            let packet = Packet::parse(&input).unwrap();
+
+            assert_eq!(packet, Packet::parse_strings(input.clone()));
+            assert_eq!(format!("{:?}", packet), input);
+        }
+    }
+
+    // cargo test --release bench_day13_parse -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_day13_parse() {
+        use super::Packet;
+        use std::time::Instant;
+
+        for depth in [10, 100, 500, 1000] {
+            let input = nested_packet(depth);
+
+            let timer = Instant::now();
+            let strings = Packet::parse_strings(input.clone());
+            let strings_time = timer.elapsed();
+
+            let timer = Instant::now();
+            let bytes = Packet::parse(&input).unwrap();
+            let bytes_time = timer.elapsed();
+
+            assert_eq!(strings, bytes);
+            println!(
+                "depth {:>4}: strings {:>12?}, bytes {:>12?}",
+                depth, strings_time, bytes_time
+            );
+        }
+    }
+
     #[test]
     fn test_day13_packet_ord() {
         use super::Packet::{List, Num};
 
         let lhs = List(vec![Num(1), Num(1), Num(3), Num(1), Num(1)]);
         let rhs = List(vec![Num(1), Num(1), Num(5), Num(1), Num(1)]);
-        assert_eq!(lhs < rhs, true);
+        assert!(lhs < rhs);
 
         let lhs = List(vec![List(vec![Num(1)]), List(vec![Num(2), Num(3), Num(4)])]);
         let rhs = List(vec![List(vec![Num(1)]), Num(4)]);
-        assert_eq!(lhs < rhs, true);
+        assert!(lhs < rhs);
 
         let lhs = List(vec![Num(9)]);
         let rhs = List(vec![List(vec![Num(8), Num(7), Num(6)])]);
-        assert_eq!(lhs < rhs, false);
+        assert!(lhs >= rhs);
+    }
+
+    // Deterministic packets with a mix of numbers, empty lists and nesting
+    fn random_packets(count: usize, seed: u64) -> Vec<String> {
+        fn random_packet(state: &mut u64, depth: usize, out: &mut String) {
+            *state ^= *state << 13;
+            *state ^= *state >> 7;
+            *state ^= *state << 17;
+
+            if depth > 0 && (depth >= 5 || (*state).is_multiple_of(3)) {
+                out.push_str(&(*state % 11).to_string());
+                return;
+            }
+
+            out.push('[');
+            for i in 0..(*state >> 8) % 5 {
+                if i > 0 {
+                    out.push(',');
+                }
+                random_packet(state, depth + 1, out);
+            }
+            out.push(']');
+        }
+
+        let mut state = seed;
+
+        (0..count)
+            .map(|_| {
+                let mut out = String::new();
+                random_packet(&mut state, 0, &mut out);
+                out
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_day13_cmp_text() {
+        use super::{cmp_text, Packet};
+
+        let mut lines: Vec<&str> = INPUT_LINES.into_iter().filter(|l| !l.is_empty()).collect();
+        let generated = random_packets(100, 13);
+        lines.extend(generated.iter().map(String::as_str));
+        lines.extend(["[[[5]]]", "[5]", "5", "[[5,[]]]", "[[],[[[]]]]"]);
+
+        for lhs in lines.iter() {
+            for rhs in lines.iter() {
+                let expected = Packet::parse(lhs)
+                    .unwrap()
+                    .cmp(&Packet::parse(rhs).unwrap());
+                assert_eq!(cmp_text(lhs, rhs), expected, "{} vs {}", lhs, rhs);
+            }
+        }
+    }
+
+    // cargo test --release bench_day13_sort -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_day13_sort() {
+        use super::{cmp_text, Packet};
+        use std::time::Instant;
+
+        let lines = random_packets(50_000, 2022);
+        let mut packets: Vec<Packet> = lines.iter().map(|l| Packet::parse(l).unwrap()).collect();
+        let mut text: Vec<&str> = lines.iter().map(String::as_str).collect();
+
+        let timer = Instant::now();
+        packets.sort();
+        let packets_time = timer.elapsed();
+
+        let timer = Instant::now();
+        text.sort_by(|lhs, rhs| cmp_text(lhs, rhs));
+        let text_time = timer.elapsed();
+
+        assert!(text
+            .iter()
+            .zip(packets.iter())
+            .all(|(line, packet)| Packet::parse(line).unwrap() == *packet));
+        println!("packets {:?}, text {:?}", packets_time, text_time);
+    }
+
+    #[test]
+    fn test_day13_probe_ranks() {
+        use super::{cmp_text, decoder_key, packet_lines, probe_ranks, DIVIDERS};
+
+        let input = INPUT_LINES.join("\n");
+        let packets = packet_lines(&input);
+
+        assert_eq!(decoder_key(&packets, &DIVIDERS), (140, vec![10, 14]));
+
+        // Ranks match the position after actually sorting
+        let probes = ["[[6]]", "[]", "[5]", "[[2]]", "[5]", "[11]"];
+        let ranks = probe_ranks(&packets, &probes);
+        assert_eq!(ranks, vec![17, 1, 15, 11, 16, 22]);
+
+        // Probes go ahead of equal packets, and equal probes keep their order
+        let mut sorted: Vec<(&str, usize)> = probes.iter().copied().zip(0..).collect();
+        sorted.extend(packets.iter().map(|&packet| (packet, probes.len())));
+        sorted.sort_by(|lhs, rhs| cmp_text(lhs.0, rhs.0).then(lhs.1.cmp(&rhs.1)));
+
+        for (i, rank) in ranks.into_iter().enumerate() {
+            assert_eq!(sorted[rank - 1], (probes[i], i));
+        }
+
+        let output = super::probes_option(&input, Some("[[2]] [[6]] [0]"));
+        assert_eq!(
+            output,
+            "[[2]] ranks 11\n[[6]] ranks 15\n[0] ranks 4\nDecoder key: 660"
+        );
+    }
+
+    #[test]
+    fn test_day13_explain() {
+        use super::Packet;
+        use super::Side::{Left, Right};
+        use super::StepKind::{RanOut, Smaller};
+        use std::cmp::Ordering;
+
+        let explain = |lhs: &str, rhs: &str| {
+            let lhs: Packet = lhs.parse().unwrap();
+            lhs.explain(&rhs.parse().unwrap())
+        };
+
+        let explanation = explain(INPUT_LINES[3], INPUT_LINES[4]);
+        let expected = [
+            "- Compare [[1],[2,3,4]] vs [[1],4]",
+            "  - Compare [1] vs [1]",
+            "    - Compare 1 vs 1",
+            "  - Compare [2,3,4] vs 4",
+            "    - Mixed types; convert right to [4] and retry comparison",
+            "    - Compare [2,3,4] vs [4]",
+            "      - Compare 2 vs 4",
+            "        - Left side is smaller, so inputs are in the right order",
+        ];
+        assert_eq!(explanation.render(), expected.join("\n"));
+        assert_eq!(explanation.ordering, Ordering::Less);
+
+        let step = explanation.deciding_step().unwrap();
+        assert_eq!(step.kind, Smaller(Left));
+        assert_eq!(step.path, vec![1, 0]);
+
+        let explanation = explain(INPUT_LINES[6], INPUT_LINES[7]);
+        let expected = [
+            "- Compare [9] vs [[8,7,6]]",
+            "  - Compare 9 vs [8,7,6]",
+            "    - Mixed types; convert left to [9] and retry comparison",
+            "    - Compare [9] vs [8,7,6]",
+            "      - Compare 9 vs 8",
+            "        - Right side is smaller, so inputs are not in the right order",
+        ];
+        assert_eq!(explanation.render(), expected.join("\n"));
+        assert_eq!(explanation.deciding_step().unwrap().kind, Smaller(Right));
+
+        let explanation = explain(INPUT_LINES[18], INPUT_LINES[19]);
+        let expected = [
+            "- Compare [[[]]] vs [[]]",
+            "  - Compare [[]] vs []",
+            "    - Right side ran out of items, so inputs are not in the right order",
+        ];
+        assert_eq!(explanation.render(), expected.join("\n"));
+        assert_eq!(explanation.deciding_step().unwrap().kind, RanOut(Right));
+        assert_eq!(explanation.deciding_step().unwrap().path, vec![0]);
+
+        let explanation = explain(INPUT_LINES[21], INPUT_LINES[22]);
+        assert_eq!(explanation.ordering, Ordering::Greater);
+        assert_eq!(explanation.steps.len(), 13);
+        assert_eq!(
+            explanation.deciding_step().unwrap().path,
+            vec![1, 1, 1, 1, 2]
+        );
+
+        let explanation = explain("[1,[2]]", "[1,[2]]");
+        assert_eq!(explanation.ordering, Ordering::Equal);
+        assert_eq!(explanation.deciding_step(), None);
+
+        // The explanation always agrees with plain comparison
+        let generated = random_packets(50, 7);
+        for lhs in generated.iter() {
+            for rhs in generated.iter() {
+                let expected = lhs.parse::<Packet>().unwrap().cmp(&rhs.parse().unwrap());
+                assert_eq!(explain(lhs, rhs).ordering, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_day13_display_round_trip() {
+        use super::Packet;
+
+        let generated = random_packets(100, 42);
+        let lines = INPUT_LINES
+            .into_iter()
+            .filter(|line| !line.is_empty())
+            .chain(generated.iter().map(String::as_str));
+
+        for line in lines {
+            let packet: Packet = line.parse().unwrap();
+            assert_eq!(packet.to_string(), line);
+            assert_eq!(packet.to_string().parse::<Packet>().unwrap(), packet);
+        }
+    }
+
+    #[test]
+    fn test_day13_pretty() {
+        use super::Packet;
+
+        let packet: Packet = "[1,[2,[3,4],[]],[5,6],7]".parse().unwrap();
+        let pretty = [
+            "[",
+            "  1,",
+            "  [",
+            "    2,",
+            "    [3,4],",
+            "    []",
+            "  ],",
+            "  [5,6],",
+            "  7",
+            "]",
+        ];
+        assert_eq!(format!("{:#}", packet), pretty.join("\n"));
+
+        let packet: Packet = "[1,2,3]".parse().unwrap();
+        assert_eq!(format!("{:#}", packet), "[1,2,3]");
+    }
+
+    #[test]
+    fn test_day13_packet_parse_whitespace() {
+        use super::Packet;
+
+        let packet = Packet::parse(" [1, [2 ,3],\n\t[ ] ] ").unwrap();
+        assert_eq!(packet.to_string(), "[1,[2,3],[]]");
+        assert_eq!(Packet::parse("[ 4 ]").unwrap().to_string(), "[4]");
+    }
+
+    #[test]
+    fn test_day13_json() {
+        use super::PacketError::UnexpectedChar;
+        use super::{packets_from_json, Packet};
+
+        // Packets read back from their own JSON, and from other tools' spacing
+        let packets: Vec<Packet> = INPUT_LINES[..5]
+            .iter()
+            .filter(|line| !line.is_empty())
+            .map(|line| line.parse().unwrap())
+            .collect();
+        let json = format!(
+            "[{}]",
+            packets
+                .iter()
+                .map(Packet::to_string)
+                .collect::<Vec<String>>()
+                .join(",")
+        );
+        assert_eq!(packets_from_json(&json), Ok(packets));
+
+        let packets = packets_from_json("[\n  [1, [2, 3]],\n  [[4], 5]\n]\n").unwrap();
+        assert_eq!(
+            packets,
+            vec!["[1,[2,3]]".parse().unwrap(), "[[4],5]".parse().unwrap()]
+        );
+
+        assert_eq!(
+            packets_from_json(" 5"),
+            Err(UnexpectedChar { pos: 1, found: '5' })
+        );
+
+        let output = super::json_option(&INPUT_LINES[..5].join("\n"), None);
+        let expected = [
+            r#"{"pair":1,"left":[1,1,3,1,1],"right":[1,1,5,1,1],"ordered":true}"#,
+            r#"{"pair":2,"left":[[1],[2,3,4]],"right":[[1],4],"ordered":true}"#,
+        ];
+        assert_eq!(output, expected.join("\n"));
     }
 
     #[test]
@@ -285,19 +1164,19 @@ mod tests {
         use super::StringExt;
 
         let string = String::from("i");
-        assert_eq!(string.is_bracketed(), false);
+        assert!(!string.is_bracketed());
 
         let string = String::from("[[");
-        assert_eq!(string.is_bracketed(), false);
+        assert!(!string.is_bracketed());
 
         let string = String::from("]]");
-        assert_eq!(string.is_bracketed(), false);
+        assert!(!string.is_bracketed());
 
         let string = String::from("[]");
-        assert_eq!(string.is_bracketed(), true);
+        assert!(string.is_bracketed());
 
         let string = String::from("[1234]");
-        assert_eq!(string.is_bracketed(), true);
+        assert!(string.is_bracketed());
     }
 
     #[test]
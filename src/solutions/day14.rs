@@ -1,53 +1,183 @@
-use std::collections::HashSet;
+use super::render::{self, Viewport};
+use super::search;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Formatter, Result};
+use std::fs;
+use std::path::Path;
 
+type Coords = (isize, isize);
+
+const SOURCE: Coords = (500, 0);
+
+// Down, then down and left, then down and right
+const FALL_RULES: [Coords; 3] = [(0, 1), (-1, 1), (1, 1)];
+
+#[derive(Debug, PartialEq)]
+enum CaveError {
+    InvalidPoint(String),
+    DiagonalLine(Coords, Coords),
+    NoRocks,
+    NoSources,
+    InvalidRule(Coords),
+}
+
+// Where sand comes from, where it can fall to and how deep the floor is,
+// `None` putting it two below the lowest rock as in the puzzle
+struct CaveConfig {
+    sources: Vec<Coords>,
+    rules: Vec<Coords>,
+    floor: Option<isize>,
+}
+
+impl Default for CaveConfig {
+    fn default() -> CaveConfig {
+        CaveConfig {
+            sources: vec![SOURCE],
+            rules: FALL_RULES.to_vec(),
+            floor: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
 enum CaveEnd {
     Abyss,
     Floor,
 }
 
-struct Cave {
-    rocks: HashSet<(usize, usize)>,
-    sand: HashSet<(usize, usize)>,
-    trail: HashSet<(usize, usize)>,
-    abyss: usize,
+// Storage for a set of cells in the cave
+trait Cells {
+    // Sized to hold at least everything from `min` to `max`
+    fn with_bounds(min: Coords, max: Coords) -> Self;
+    fn contains(&self, coords: &Coords) -> bool;
+    fn insert(&mut self, coords: Coords);
+    fn clear(&mut self);
+    fn len(&self) -> usize;
+    fn iter(&self) -> Box<dyn Iterator<Item = Coords> + '_>;
+}
+
+impl Cells for HashSet<Coords> {
+    fn with_bounds(_min: Coords, _max: Coords) -> Self {
+        HashSet::new()
+    }
+
+    fn contains(&self, coords: &Coords) -> bool {
+        HashSet::contains(self, coords)
+    }
+
+    fn insert(&mut self, coords: Coords) {
+        HashSet::insert(self, coords);
+    }
+
+    fn clear(&mut self) {
+        HashSet::clear(self)
+    }
+
+    fn len(&self) -> usize {
+        HashSet::len(self)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Coords> + '_> {
+        Box::new(HashSet::iter(self).copied())
+    }
+}
+
+// One bit per cell over a fixed rectangle. Cells outside it are never set.
+struct Bitmap {
+    min: Coords,
+    width: usize,
+    height: usize,
+    bits: Vec<u64>,
+    count: usize,
 }
 
-impl Debug for Cave {
+impl Bitmap {
+    fn index(&self, &(x, y): &Coords) -> Option<usize> {
+        let dx = usize::try_from(x - self.min.0).ok()?;
+        let dy = usize::try_from(y - self.min.1).ok()?;
+
+        (dx < self.width && dy < self.height).then_some(dy * self.width + dx)
+    }
+}
+
+impl Cells for Bitmap {
+    fn with_bounds(min: Coords, max: Coords) -> Self {
+        let (width, height) = (min.0.abs_diff(max.0) + 1, min.1.abs_diff(max.1) + 1);
+
+        Bitmap {
+            min,
+            width,
+            height,
+            bits: vec![0; (width * height).div_ceil(64)],
+            count: 0,
+        }
+    }
+
+    fn contains(&self, coords: &Coords) -> bool {
+        self.index(coords)
+            .is_some_and(|i| self.bits[i / 64] & (1 << (i % 64)) != 0)
+    }
+
+    fn insert(&mut self, coords: Coords) {
+        let i = self
+            .index(&coords)
+            .unwrap_or_else(|| panic!("{:?} is outside the bitmap", coords));
+
+        if self.bits[i / 64] & (1 << (i % 64)) == 0 {
+            self.bits[i / 64] |= 1 << (i % 64);
+            self.count += 1;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.bits.fill(0);
+        self.count = 0;
+    }
+
+    fn len(&self) -> usize {
+        self.count
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Coords> + '_> {
+        Box::new(
+            (0..self.width * self.height)
+                .filter(|i| self.bits[i / 64] & (1 << (i % 64)) != 0)
+                .map(|i| {
+                    let (dx, dy) = (i % self.width, i / self.width);
+                    (self.min.0 + dx as isize, self.min.1 + dy as isize)
+                }),
+        )
+    }
+}
+
+type HashCells = HashSet<Coords>;
+
+struct Cave<C: Cells = HashCells> {
+    rocks: C,
+    sand: C,
+    trail: C,
+    // Depth of the lowest rock, past which sand falls forever
+    abyss: isize,
+    // Sand comes to rest above this without one
+    floor: isize,
+    sources: Vec<Coords>,
+    // Offsets a grain tries in order, each moving it down
+    rules: Vec<Coords>,
+    // Where the last grain from each source fell through
+    paths: HashMap<Coords, Vec<Coords>>,
+    // Everywhere sand can reach, with or without the floor
+    bounds: Viewport,
+}
+
+impl<C: Cells> Debug for Cave<C> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         let mut out = String::new();
         out.push_str("Cave {\n");
         out.push_str(&format!("    abyss = {}\n", self.abyss));
         out.push_str("    map = {\n");
 
-        let min_x_rocks = *self.rocks.iter().map(|(x, _)| x).min().unwrap();
-        let max_x_rocks = *self.rocks.iter().map(|(x, _)| x).max().unwrap();
-        let max_y_rocks = *self.rocks.iter().map(|(_, y)| y).max().unwrap();
-
-        let min_x_sand = *self.sand.iter().map(|(x, _)| x).min().unwrap();
-        let max_x_sand = *self.sand.iter().map(|(x, _)| x).max().unwrap();
-        let max_y_sand = *self.sand.iter().map(|(_, y)| y).max().unwrap();
-
-        let min_x = min_x_rocks.min(min_x_sand);
-        let max_x = max_x_rocks.max(max_x_sand);
-        let max_y = max_y_rocks.max(max_y_sand);
-
-        for y in 0..=max_y {
-            out.push_str("        ");
-            for x in min_x..=max_x {
-                let coords = (x, y);
-                let ch = if self.rocks.contains(&coords) {
-                    '#'
-                } else if self.sand.contains(&coords) {
-                    'o'
-                } else if self.trail.contains(&coords) {
-                    '~'
-                } else {
-                    '.'
-                };
-                out.push(ch);
-            }
-            out.push('\n');
+        for line in self.render(self.fit()).lines() {
+            out.push_str(&format!("        {}\n", line));
         }
 
         out.push_str("    }\n");
@@ -57,144 +187,557 @@ impl Debug for Cave {
     }
 }
 
-impl Cave {
-    fn parse(input: &str) -> Cave {
-        let rocks: HashSet<(usize, usize)> = input
-            .lines()
-            .flat_map(|line| {
-                line.split(" -> ")
-                    .map(|pair| pair.split(',').collect::<Vec<&str>>())
-                    .map(|pair_vec| {
-                        let x = pair_vec[0].parse().unwrap();
-                        let y = pair_vec[1].parse().unwrap();
-                        (x, y)
-                    })
-                    .collect::<Vec<(usize, usize)>>()
-                    .windows(2)
-                    .flat_map(|pair| Self::coord_travel(pair[0], pair[1]))
-                    .collect::<Vec<(usize, usize)>>()
-            })
-            .collect();
+impl<C: Cells> Cave<C> {
+    fn parse(input: &str) -> std::result::Result<Cave<C>, CaveError> {
+        Self::parse_with(input, CaveConfig::default())
+    }
+
+    fn parse_with(input: &str, config: CaveConfig) -> std::result::Result<Cave<C>, CaveError> {
+        let mut rock_coords: Vec<Coords> = Vec::new();
+
+        for line in input.lines().map(|line| line.trim()) {
+            let points = line
+                .split(" -> ")
+                .map(|point| {
+                    point
+                        .split_once(',')
+                        .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)))
+                        .ok_or_else(|| CaveError::InvalidPoint(point.to_string()))
+                })
+                .collect::<std::result::Result<Vec<Coords>, CaveError>>()?;
+
+            for pair in points.windows(2) {
+                rock_coords.extend(Self::coord_travel(pair[0], pair[1])?);
+            }
+
+            if let [point] = points[..] {
+                rock_coords.push(point);
+            }
+        }
+
+        if config.sources.is_empty() {
+            return Err(CaveError::NoSources);
+        }
+
+        if let Some(&rule) = config.rules.iter().find(|(_, dy)| *dy < 1) {
+            return Err(CaveError::InvalidRule(rule));
+        }
 
-        let abyss = *rocks.iter().map(|(_, y)| y).max().unwrap();
+        let abyss = rock_coords
+            .iter()
+            .map(|(_, y)| *y)
+            .max()
+            .ok_or(CaveError::NoRocks)?;
+        let floor = config.floor.unwrap_or(abyss + 2);
 
-        Cave {
+        // A grain above the abyss can still step past it by the deepest
+        // rule before it's dropped, but never reaches the floor. Each step
+        // down moves sand at most the widest rule sideways, so that bounds
+        // how far it spreads before reaching the bottom.
+        let deepest = config.rules.iter().map(|(_, dy)| *dy).max().unwrap_or(1);
+        let bottom = (abyss + deepest - 1).max(floor - 1);
+        let widest = config
+            .rules
+            .iter()
+            .map(|(dx, _)| dx.abs())
+            .max()
+            .unwrap_or(0);
+
+        let spread = |&(x, y): &Coords| {
+            let reach = widest * (bottom - y).max(0);
+            [(x - reach, y), (x + reach, bottom)]
+        };
+
+        let all = rock_coords
+            .iter()
+            .copied()
+            .chain(config.sources.iter().flat_map(spread));
+
+        let (mut min, mut max) = (config.sources[0], config.sources[0]);
+        for (x, y) in all {
+            min = (min.0.min(x - 1), min.1.min(y));
+            max = (max.0.max(x + 1), max.1.max(y));
+        }
+
+        let mut rocks = C::with_bounds(min, max);
+        for coords in rock_coords {
+            rocks.insert(coords);
+        }
+
+        Ok(Cave {
             rocks,
             abyss,
-            sand: HashSet::new(),
-            trail: HashSet::new(),
-        }
+            floor,
+            sources: config.sources,
+            rules: config.rules,
+            sand: C::with_bounds(min, max),
+            trail: C::with_bounds(min, max),
+            paths: HashMap::new(),
+            bounds: Viewport { min, max },
+        })
     }
 
-    fn coord_travel(start: (usize, usize), end: (usize, usize)) -> Vec<(usize, usize)> {
+    fn coord_travel(start: Coords, end: Coords) -> std::result::Result<Vec<Coords>, CaveError> {
         let (x1, y1) = start;
         let (x2, y2) = end;
 
-        if x1 == x2 && y1 == y2 {
-            return vec![start];
-        }
-
         if x1 == x2 {
             let range = if y1 > y2 { y2..=y1 } else { y1..=y2 };
-            return range.map(|y| (x1, y)).collect();
+            return Ok(range.map(|y| (x1, y)).collect());
         }
 
         if y1 == y2 {
             let range = if x1 > x2 { x2..=x1 } else { x1..=x2 };
-            return range.map(|x| (x, y1)).collect();
+            return Ok(range.map(|x| (x, y1)).collect());
         }
 
-        unreachable!()
+        Err(CaveError::DiagonalLine(start, end))
+    }
+
+    fn is_free(&self, coords: &Coords, end: CaveEnd) -> bool {
+        let above_floor = match end {
+            CaveEnd::Abyss => true,
+            CaveEnd::Floor => coords.1 < self.floor,
+        };
+
+        above_floor && !self.rocks.contains(coords) && !self.sand.contains(coords)
     }
 
-    fn drop_sand(&mut self, start: (usize, usize), end: CaveEnd) -> Option<(usize, usize)> {
-        let (mut x, mut y) = start;
+    // Where a grain at `coords` moves next, if anywhere
+    fn next_move(&self, (x, y): Coords, end: CaveEnd) -> Option<Coords> {
+        self.rules
+            .iter()
+            .map(|(dx, dy)| (x + dx, y + dy))
+            .find(|coords| self.is_free(coords, end))
+    }
+
+    fn drop_sand(&mut self, start: Coords, end: CaveEnd) -> Option<Coords> {
+        let mut coords = start;
 
         self.trail.clear();
 
+        if !self.is_free(&start, end) {
+            return None; // source blocked
+        }
+
         loop {
-            match end {
-                CaveEnd::Abyss => {
-                    if y == self.abyss {
-                        return None; // not settled
-                    }
+            if let CaveEnd::Abyss = end {
+                if coords.1 >= self.abyss {
+                    return None; // not settled
                 }
-                CaveEnd::Floor => {
-                    if y == self.abyss + 1 {
-                        self.sand.insert((x, y));
-                        return Some((x, y)); // settled on floor
-                    }
+            }
+
+            match self.next_move(coords, end) {
+                Some(next) => {
+                    coords = next;
+                    self.trail.insert(coords);
+                }
+                None => {
+                    self.sand.insert(coords);
+                    return Some(coords); // settled
                 }
             }
+        }
+    }
+}
 
-            let below = (x, y + 1);
-            let rock_below = self.rocks.contains(&below);
-            let sand_below = self.sand.contains(&below);
+impl<C: Cells> Cave<C> {
+    // Same as `drop_sand`, but each grain follows the previous one from the
+    // same start and only starts falling from the last cell on that path
+    // that's still free. Doesn't record a trail.
+    fn drop_sand_resume(&mut self, start: Coords, end: CaveEnd) -> Option<Coords> {
+        let mut path = self.paths.remove(&start).unwrap_or_default();
+
+        // Sand from other sources can land anywhere along the path
+        if let Some(blocked) = path.iter().position(|coords| !self.is_free(coords, end)) {
+            path.truncate(blocked);
+        }
 
-            if !rock_below && !sand_below {
-                y += 1;
-                self.trail.insert((x, y));
-                continue;
+        if path.is_empty() {
+            if !self.is_free(&start, end) {
+                return None; // source blocked
             }
 
-            let below_left = (x - 1, y + 1);
-            let rock_below_left = self.rocks.contains(&below_left);
-            let sand_below_left = self.sand.contains(&below_left);
+            path.push(start);
+        }
 
-            if !rock_below_left && !sand_below_left {
-                y += 1;
-                x -= 1;
-                self.trail.insert((x, y));
-                continue;
-            }
+        let settled = loop {
+            let coords = *path.last().unwrap();
 
-            let below_right = (x + 1, y + 1);
-            let rock_below_right = self.rocks.contains(&below_right);
-            let sand_below_right = self.sand.contains(&below_right);
+            if let CaveEnd::Abyss = end {
+                if coords.1 >= self.abyss {
+                    break None; // not settled
+                }
+            }
 
-            if !rock_below_right && !sand_below_right {
-                y += 1;
-                x += 1;
-                self.trail.insert((x, y));
-                continue;
+            match self.next_move(coords, end) {
+                Some(next) => path.push(next),
+                None => {
+                    self.sand.insert(coords);
+                    path.pop();
+                    break Some(coords); // settled
+                }
             }
+        };
+
+        self.paths.insert(start, path);
+        settled
+    }
+
+    fn clear_sand(&mut self) {
+        self.sand.clear();
+        self.trail.clear();
+        self.paths.clear();
+    }
+
+    // Drops grains from each source in turn until none of them can add any
+    // more, a source stopping once its grain falls into the abyss or it's
+    // buried. Returns how many grains came to rest.
+    fn fill(
+        &mut self,
+        end: CaveEnd,
+        drop: fn(&mut Self, Coords, CaveEnd) -> Option<Coords>,
+    ) -> usize {
+        let before = self.sand.len();
+        let mut active = self.sources.clone();
 
-            self.sand.insert((x, y));
-            return Some((x, y)); // settled
+        while !active.is_empty() {
+            active.retain(|&source| drop(self, source, end).is_some());
         }
+
+        self.sand.len() - before
     }
-}
 
-pub fn day14(input: &str) -> (String, String) {
-    let mut cave = Cave::parse(input);
+    // How many grains come to rest on the floor before every source is
+    // buried, starting from an empty cave. A grain only stops once every
+    // cell it could move to is filled, so in the end every cell a grain can
+    // reach is, and this just counts them.
+    fn floor_fill_count(&self) -> usize {
+        let open = |coords: &Coords| coords.1 < self.floor && !self.rocks.contains(coords);
 
-    let start: (usize, usize) = (500, 0);
+        search::distances(self.sources.iter().copied().filter(open), |&(x, y)| {
+            self.rules
+                .iter()
+                .map(move |(dx, dy)| (x + dx, y + dy))
+                .filter(open)
+                .collect::<Vec<Coords>>()
+        })
+        .len()
+    }
+}
 
-    loop {
-        match cave.drop_sand(start, CaveEnd::Abyss) {
-            None => break,
-            Some(_) => continue,
+impl<C: Cells> Cave<C> {
+    fn cell(&self, coords: &Coords) -> char {
+        if self.rocks.contains(coords) {
+            '#'
+        } else if self.sand.contains(coords) {
+            'o'
+        } else if self.trail.contains(coords) {
+            '~'
+        } else if self.sources.contains(coords) {
+            '+'
+        } else {
+            '.'
         }
     }
 
-    let part1 = cave.sand.len().to_string();
+    // Smallest viewport containing the sources and everything in the cave
+    fn fit(&self) -> Viewport {
+        let all = self
+            .rocks
+            .iter()
+            .chain(self.sand.iter())
+            .chain(self.trail.iter())
+            .chain(self.sources.iter().copied());
 
-    loop {
-        match cave.drop_sand(start, CaveEnd::Floor) {
-            None => unreachable!(),
-            Some(settled_at) => {
-                if settled_at == start {
-                    break;
+        Viewport::around(self.sources[0], all)
+    }
+
+    // Anything outside the viewport is left out
+    fn render(&self, viewport: Viewport) -> String {
+        let cells: Vec<char> = viewport.cells().map(|coords| self.cell(&coords)).collect();
+
+        cells
+            .chunks(viewport.width())
+            .map(|row| row.iter().collect::<String>())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    // Same as `render` with each cell coloured for a terminal
+    fn render_ansi(&self, viewport: Viewport) -> String {
+        self.render(viewport)
+            .lines()
+            .map(|line| {
+                let coloured: String = line
+                    .chars()
+                    .map(|ch| match ch {
+                        '#' => "\x1b[90m#".to_string(),
+                        'o' => "\x1b[33mo".to_string(),
+                        '~' => "\x1b[36m~".to_string(),
+                        '+' => "\x1b[31m+".to_string(),
+                        ch => format!("\x1b[0m{}", ch),
+                    })
+                    .collect();
+                format!("{}\x1b[0m", coloured)
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn render_ppm(&self, viewport: Viewport, scale: usize) -> Vec<u8> {
+        let cols = viewport.width();
+
+        let cells: Vec<[u8; 3]> = viewport
+            .cells()
+            .map(|coords| match self.cell(&coords) {
+                '#' => [120, 120, 120],
+                'o' => [230, 200, 90],
+                '~' => [90, 170, 230],
+                '+' => [230, 60, 60],
+                _ => [20, 20, 20],
+            })
+            .collect();
+
+        render::ppm(&cells, cols, scale)
+    }
+}
+
+// Drops grains into the abyss and then onto the floor until the sources
+// are buried, emptying the cave in between as `simulate` does. `frame` sees
+// the cave before the first grain, after every `every` grains with the last
+// grain's trail, and once each part is done.
+fn animate<C: Cells>(cave: &mut Cave<C>, every: usize, mut frame: impl FnMut(&Cave<C>)) {
+    let mut grains: usize = 0;
+
+    frame(cave);
+
+    for end in [CaveEnd::Abyss, CaveEnd::Floor] {
+        if let CaveEnd::Floor = end {
+            cave.clear_sand();
+        }
+
+        let mut active = cave.sources.clone();
+
+        while !active.is_empty() {
+            active.retain(|&source| {
+                if !cave.is_free(&source, end) {
+                    return false;
                 }
-            }
+
+                let settled = cave.drop_sand(source, end);
+                grains += 1;
+
+                if grains.is_multiple_of(every) {
+                    frame(cave);
+                }
+
+                settled.is_some()
+            });
+        }
+
+        if !grains.is_multiple_of(every) {
+            frame(cave);
         }
     }
+}
 
-    let part2 = cave.sand.len().to_string();
+// Counts of sand resting with the abyss and then with the floor, dropping
+// each grain step by step. The floor starts from an empty cave, as a floor
+// above the lowest rock would be under some of the first part's sand.
+fn simulate<C: Cells>(cave: &mut Cave<C>) -> (usize, usize) {
+    let abyss_count = cave.fill(CaveEnd::Abyss, Cave::drop_sand);
+    cave.clear_sand();
+    let floor_count = cave.fill(CaveEnd::Floor, Cave::drop_sand);
+
+    (abyss_count, floor_count)
+}
+
+pub fn day14(input: &str) -> (String, String) {
+    let mut cave: Cave<Bitmap> = Cave::parse(input).unwrap();
+
+    let part1 = cave
+        .fill(CaveEnd::Abyss, Cave::drop_sand_resume)
+        .to_string();
+    let part2 = cave.floor_fill_count().to_string();
 
     (part1, part2)
 }
 
+// Compares the counts from the step by step simulation with resuming from
+// the last path and, for the floor, filling
+pub fn verify_option(input: &str, _value: Option<&str>) -> String {
+    let (abyss_count, floor_count) = match Cave::<Bitmap>::parse(input) {
+        Ok(mut cave) => simulate(&mut cave),
+        Err(err) => return format!("{:?}", err),
+    };
+
+    let mut cave: Cave<Bitmap> = Cave::parse(input).unwrap();
+    let resumed_abyss = cave.fill(CaveEnd::Abyss, Cave::drop_sand_resume);
+    cave.clear_sand();
+    let resumed_floor = cave.fill(CaveEnd::Floor, Cave::drop_sand_resume);
+
+    let filled_floor = cave.floor_fill_count();
+
+    let check = |matches: bool| if matches { "ok" } else { "MISMATCH" };
+
+    [
+        format!("Simulated: abyss {}, floor {}", abyss_count, floor_count),
+        format!(
+            "Resumed:   abyss {}, floor {} ({})",
+            resumed_abyss,
+            resumed_floor,
+            check((resumed_abyss, resumed_floor) == (abyss_count, floor_count))
+        ),
+        format!(
+            "Filled:    floor {} ({})",
+            filled_floor,
+            check(filled_floor == floor_count)
+        ),
+    ]
+    .join("\n")
+}
+
+// The cave once sand starts falling into the abyss, within an optional
+// min_x,min_y,max_x,max_y viewport
+pub fn render_option(input: &str, value: Option<&str>) -> String {
+    let mut cave: Cave<Bitmap> = match Cave::parse(input) {
+        Ok(cave) => cave,
+        Err(err) => return format!("{:?}", err),
+    };
+
+    cave.fill(CaveEnd::Abyss, Cave::drop_sand_resume);
+
+    match value.map(Viewport::parse) {
+        None => cave.render(cave.fit()),
+        Some(Ok(viewport)) => cave.render(viewport),
+        Some(Err(err)) => err,
+    }
+}
+
+// Terminal replay of the sand falling, one frame every `every` grains, as
+// `every` or `every,min_x,min_y,max_x,max_y`
+pub fn animate_option(input: &str, value: Option<&str>) -> String {
+    let mut cave: Cave<Bitmap> = match Cave::parse(input) {
+        Ok(cave) => cave,
+        Err(err) => return format!("{:?}", err),
+    };
+
+    let (every, viewport) = match value.map(|value| value.split_once(',')) {
+        None => (Some(1), Ok(cave.bounds)),
+        Some(None) => (value.unwrap().parse().ok(), Ok(cave.bounds)),
+        Some(Some((every, viewport))) => (every.parse().ok(), Viewport::parse(viewport)),
+    };
+
+    let Some(every) = every.filter(|&every| every > 0) else {
+        return format!("Invalid grain count in {:?}", value.unwrap());
+    };
+    let viewport = match viewport {
+        Ok(viewport) => viewport,
+        Err(err) => return err,
+    };
+
+    let mut frames: Vec<String> = Vec::new();
+
+    animate(&mut cave, every, |cave| {
+        frames.push(format!(
+            "\x1b[H\x1b[2J{}\nSand: {}",
+            cave.render_ansi(viewport),
+            cave.sand.len()
+        ))
+    });
+
+    frames.join("\n")
+}
+
+// PPM frames of the sand falling written to a directory, as `dir[,every]`
+pub fn animate_ppm_option(input: &str, value: Option<&str>) -> String {
+    let (dir, every) = match value.unwrap_or("frames").split_once(',') {
+        None => (value.unwrap_or("frames"), Some(1)),
+        Some((dir, every)) => (dir, every.parse::<usize>().ok().filter(|&every| every > 0)),
+    };
+
+    let Some(every) = every else {
+        return format!("Invalid grain count in {:?}", value.unwrap());
+    };
+
+    let mut cave: Cave<Bitmap> = match Cave::parse(input) {
+        Ok(cave) => cave,
+        Err(err) => return format!("{:?}", err),
+    };
+
+    let dir = Path::new(dir);
+
+    if let Err(err) = fs::create_dir_all(dir) {
+        return format!("Could not create {}: {}", dir.display(), err);
+    }
+    let viewport = cave.bounds;
+    let mut written: usize = 0;
+    let mut failed: Option<String> = None;
+
+    animate(&mut cave, every, |cave| {
+        if failed.is_some() {
+            return;
+        }
+
+        let path = dir.join(format!("frame_{:05}.ppm", written));
+
+        match fs::write(&path, cave.render_ppm(viewport, 2)) {
+            Ok(()) => written += 1,
+            Err(err) => failed = Some(format!("Could not write {}: {}", path.display(), err)),
+        }
+    });
+
+    failed.unwrap_or_else(|| format!("Wrote {} frames to {}", written, dir.display()))
+}
+
+// Sand counts for a variant of the cave, given as `;` separated settings:
+// `sources=x,y x,y ...`, `floor=y` and `rules=dx,dy dx,dy ...`
+pub fn variant_option(input: &str, value: Option<&str>) -> String {
+    let mut config = CaveConfig::default();
+
+    let points = |value: &str| {
+        value
+            .split_whitespace()
+            .map(|point| {
+                let (x, y) = point.split_once(',')?;
+                Some((x.parse().ok()?, y.parse().ok()?))
+            })
+            .collect::<Option<Vec<Coords>>>()
+    };
+
+    for setting in value
+        .unwrap_or_default()
+        .split(';')
+        .filter(|s| !s.trim().is_empty())
+    {
+        let parsed = match setting.trim().split_once('=') {
+            Some(("sources", value)) => points(value).map(|sources| config.sources = sources),
+            Some(("rules", value)) => points(value).map(|rules| config.rules = rules),
+            Some(("floor", value)) => value.parse().ok().map(|floor| config.floor = Some(floor)),
+            _ => None,
+        };
+
+        if parsed.is_none() {
+            return format!("Invalid setting {:?}", setting);
+        }
+    }
+
+    let mut cave: Cave<Bitmap> = match Cave::parse_with(input, config) {
+        Ok(cave) => cave,
+        Err(err) => return format!("{:?}", err),
+    };
+
+    let abyss_count = cave.fill(CaveEnd::Abyss, Cave::drop_sand_resume);
+
+    format!(
+        "Abyss: {}, Floor at {}: {}",
+        abyss_count,
+        cave.floor,
+        cave.floor_fill_count()
+    )
+}
+
 #[cfg(test)]
 mod tests {
     const INPUT_LINES: [&str; 2] = [
@@ -210,4 +753,340 @@ mod tests {
         assert_eq!(part1, "24");
         assert_eq!(part2, "93");
     }
+
+    #[test]
+    fn test_day14_backends() {
+        use super::{simulate, Bitmap, Cave, Cells, Coords, HashCells};
+
+        let input = INPUT_LINES.join("\n");
+
+        let mut hashed: Cave<HashCells> = Cave::parse(&input).unwrap();
+        let mut dense: Cave<Bitmap> = Cave::parse(&input).unwrap();
+        assert_eq!(simulate(&mut hashed), (24, 93));
+        assert_eq!(simulate(&mut dense), (24, 93));
+
+        let mut hashed_sand: Vec<Coords> = Cells::iter(&hashed.sand).collect();
+        hashed_sand.sort_by_key(|&(x, y)| (y, x));
+        assert_eq!(dense.sand.iter().collect::<Vec<_>>(), hashed_sand);
+        assert_eq!(format!("{:?}", dense), format!("{:?}", hashed));
+    }
+
+    #[test]
+    fn test_day14_bitmap() {
+        use super::{Bitmap, Cells};
+
+        let mut bitmap = Bitmap::with_bounds((10, 2), (19, 9));
+        assert_eq!(bitmap.bits.len(), 2);
+
+        for coords in [(10, 2), (19, 9), (15, 5), (15, 5)] {
+            bitmap.insert(coords);
+        }
+
+        assert_eq!(bitmap.len(), 3);
+        assert!(bitmap.contains(&(15, 5)));
+        assert!(!bitmap.contains(&(16, 5)));
+        assert!(!bitmap.contains(&(9, 2)));
+        assert!(!bitmap.contains(&(20, 9)));
+        assert!(!bitmap.contains(&(10, 10)));
+        assert_eq!(
+            bitmap.iter().collect::<Vec<_>>(),
+            [(10, 2), (15, 5), (19, 9)]
+        );
+
+        bitmap.clear();
+        assert_eq!(bitmap.len(), 0);
+        assert_eq!(bitmap.iter().count(), 0);
+    }
+
+    // cargo test --release bench_day14_backends -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_day14_backends() {
+        use super::{simulate, Bitmap, Cave, HashCells};
+        use std::time::Instant;
+
+        let input = std::fs::read_to_string("input/day14.txt").unwrap();
+
+        let timer = Instant::now();
+        let hashed = simulate(&mut Cave::<HashCells>::parse(&input).unwrap());
+        let hashed_time = timer.elapsed();
+
+        let timer = Instant::now();
+        let dense = simulate(&mut Cave::<Bitmap>::parse(&input).unwrap());
+        let dense_time = timer.elapsed();
+
+        assert_eq!(hashed, dense);
+        println!("hashed {:?}, dense {:?}", hashed_time, dense_time);
+    }
+
+    #[test]
+    fn test_day14_resume_and_fill() {
+        use super::{simulate, Bitmap, Cave, CaveEnd, Cells, SOURCE};
+
+        let caves = [
+            INPUT_LINES.join("\n"),
+            // A cup that fills before the rest of the cave
+            "498,6 -> 498,8 -> 502,8 -> 502,6\n494,14 -> 506,14".to_string(),
+            // A shelf under the source with a gap to fall through
+            "497,2 -> 499,2\n501,2 -> 503,2\n495,12 -> 505,12".to_string(),
+        ];
+
+        for input in caves {
+            let expected = simulate(&mut Cave::<Bitmap>::parse(&input).unwrap());
+
+            let mut cave: Cave<Bitmap> = Cave::parse(&input).unwrap();
+            let mut settled = Vec::new();
+            while let Some(coords) = cave.drop_sand_resume(SOURCE, CaveEnd::Abyss) {
+                settled.push(coords);
+            }
+            let abyss_count = cave.sand.len();
+            while let Some(coords) = cave.drop_sand_resume(SOURCE, CaveEnd::Floor) {
+                settled.push(coords);
+            }
+
+            assert_eq!((abyss_count, cave.sand.len()), expected, "{}", input);
+            assert_eq!(settled.last(), Some(&SOURCE));
+            assert_eq!(cave.floor_fill_count(), expected.1, "{}", input);
+
+            // Grains settle in the same order as the simulation
+            let mut stepped: Cave<Bitmap> = Cave::parse(&input).unwrap();
+            for coords in settled.iter().take(abyss_count) {
+                assert_eq!(stepped.drop_sand(SOURCE, CaveEnd::Abyss), Some(*coords));
+            }
+        }
+
+        let output = super::verify_option(&INPUT_LINES.join("\n"), None);
+        assert!(!output.contains("MISMATCH"), "{}", output);
+    }
+
+    #[test]
+    fn test_day14_render() {
+        use super::{Cave, CaveEnd, HashCells};
+
+        let input = INPUT_LINES.join("\n");
+        let mut cave: Cave<HashCells> = Cave::parse(&input).unwrap();
+
+        // Nothing has fallen yet
+        let empty = [
+            "......+...",
+            "..........",
+            "..........",
+            "..........",
+            "....#...##",
+            "....#...#.",
+            "..###...#.",
+            "........#.",
+            "........#.",
+            "#########.",
+        ];
+        assert_eq!(cave.render(cave.fit()), empty.join("\n"));
+        assert!(format!("{:?}", cave).contains("        ......+..."));
+
+        cave.fill(CaveEnd::Abyss, Cave::drop_sand_resume);
+
+        let filled = [
+            "......+...",
+            "..........",
+            "......o...",
+            ".....ooo..",
+            "....#ooo##",
+            "...o#ooo#.",
+            "..###ooo#.",
+            "....oooo#.",
+            ".o.ooooo#.",
+            "#########.",
+        ];
+        assert_eq!(super::render_option(&input, None), filled.join("\n"));
+
+        // Viewports can reach past everything in the cave
+        let output = super::render_option(&input, Some("499,0,501,2"));
+        assert_eq!(output, ".+.\n...\n.o.");
+        let output = super::render_option(&input, Some("0,0,1,0"));
+        assert_eq!(output, "..");
+        let output = super::render_option(&input, Some("5,0,1,0"));
+        assert!(output.starts_with("Invalid viewport"));
+    }
+
+    #[test]
+    fn test_day14_animate() {
+        use super::{animate, Cave, HashCells, Viewport};
+
+        let input = INPUT_LINES.join("\n");
+        let mut cave: Cave<HashCells> = Cave::parse(&input).unwrap();
+        let viewport = Viewport::parse("494,0,503,9").unwrap();
+
+        let mut frames: Vec<String> = Vec::new();
+        animate(&mut cave, 5, |cave| frames.push(cave.render(viewport)));
+
+        // 25 grains for the abyss, the last falling out, and 93 onto the
+        // floor in the emptied cave. That's the empty cave, every 5th grain
+        // and the last.
+        assert_eq!(frames.len(), 1 + 118 / 5 + 1);
+        assert!(!frames[0].contains('o'));
+
+        // The fifth grain is drawn with its trail down from the source
+        let fifth = [
+            "......+...",
+            "......~...",
+            "......~...",
+            "......~...",
+            "....#.~.##",
+            "....#.~.#.",
+            "..###.~.#.",
+            ".....~o.#.",
+            "....oooo#.",
+            "#########.",
+        ];
+        assert_eq!(frames[1], fifth.join("\n"));
+
+        let output = super::animate_option(&input, Some("100"));
+        // The empty cave, the end of each part and the 100th grain
+        assert_eq!(output.matches("\x1b[H\x1b[2J").count(), 4);
+        assert!(output.ends_with("Sand: 93"));
+        assert!(super::animate_option(&input, Some("0")).starts_with("Invalid"));
+        assert!(super::animate_option(&input, Some("1,2,3")).starts_with("Invalid"));
+
+        // A floor above the lowest rock ends up the same as `simulate`
+        let config = || super::CaveConfig {
+            floor: Some(3),
+            ..super::CaveConfig::default()
+        };
+        let mut animated: Cave<HashCells> = Cave::parse_with(&input, config()).unwrap();
+        let mut sand_counts: Vec<usize> = Vec::new();
+        animate(&mut animated, 1, |cave| sand_counts.push(cave.sand.len()));
+        let mut simulated: Cave<HashCells> = Cave::parse_with(&input, config()).unwrap();
+        assert_eq!(sand_counts.last(), Some(&super::simulate(&mut simulated).1));
+
+        let ppm = cave.render_ppm(viewport, 3);
+        assert!(ppm.starts_with(b"P6\n30 30\n255\n"));
+        assert_eq!(ppm.len(), "P6\n30 30\n255\n".len() + 30 * 30 * 3);
+    }
+
+    #[test]
+    fn test_day14_parse_errors() {
+        use super::CaveError::{DiagonalLine, InvalidPoint, InvalidRule, NoRocks, NoSources};
+        use super::{Cave, CaveConfig, HashCells};
+
+        let parse = |input: &str| Cave::<HashCells>::parse(input).map(|_| ());
+        assert_eq!(parse("1,2 -> 1,x"), Err(InvalidPoint("1,x".to_string())));
+        assert_eq!(parse("1,2 -> 3"), Err(InvalidPoint("3".to_string())));
+        assert_eq!(parse("1,2 -> 3,4"), Err(DiagonalLine((1, 2), (3, 4))));
+        assert_eq!(parse(""), Err(NoRocks));
+
+        let config = CaveConfig {
+            sources: Vec::new(),
+            ..CaveConfig::default()
+        };
+        let cave = Cave::<HashCells>::parse_with("1,2 -> 1,4", config);
+        assert_eq!(cave.map(|_| ()), Err(NoSources));
+
+        let config = CaveConfig {
+            rules: vec![(0, 1), (1, 0)],
+            ..CaveConfig::default()
+        };
+        let cave = Cave::<HashCells>::parse_with("1,2 -> 1,4", config);
+        assert_eq!(cave.map(|_| ()), Err(InvalidRule((1, 0))));
+    }
+
+    #[test]
+    fn test_day14_signed_coords() {
+        use super::{simulate, Bitmap, Cave, CaveConfig, HashCells};
+
+        // Sand spilling off a ledge at x = 0 and into negative x
+        let input = "0,3 -> 2,3\n-4,6 -> 4,6";
+        let config = || CaveConfig {
+            sources: vec![(1, 0)],
+            ..CaveConfig::default()
+        };
+
+        let mut hashed: Cave<HashCells> = Cave::parse_with(input, config()).unwrap();
+        let mut dense: Cave<Bitmap> = Cave::parse_with(input, config()).unwrap();
+
+        let counts = simulate(&mut hashed);
+        assert_eq!(simulate(&mut dense), counts);
+        assert_eq!(format!("{:?}", dense), format!("{:?}", hashed));
+
+        let rendered = [
+            ".....+...",
+            ".........",
+            "....oo...",
+            "...o###..",
+            "..ooo....",
+            ".ooooooo.",
+            "#########",
+        ];
+        let mut cave: Cave<HashCells> = Cave::parse_with(input, config()).unwrap();
+        cave.fill(super::CaveEnd::Abyss, Cave::drop_sand_resume);
+        assert_eq!(cave.render(cave.fit()), rendered.join("\n"));
+        assert_eq!(cave.floor_fill_count(), counts.1);
+    }
+
+    #[test]
+    fn test_day14_sources_floor_rules() {
+        use super::{simulate, Bitmap, Cave, CaveConfig, CaveEnd, HashCells};
+
+        let input = INPUT_LINES.join("\n");
+        let check = |config: CaveConfig| {
+            let mut cave: Cave<Bitmap> = Cave::parse_with(&input, config).unwrap();
+            let (abyss_count, floor_count) = simulate(&mut cave);
+            assert_eq!(cave.floor_fill_count(), floor_count);
+            (abyss_count, floor_count)
+        };
+
+        assert_eq!(check(CaveConfig::default()), (24, 93));
+
+        // A shallower floor leaves less room, and one above the rocks
+        // catches everything before it reaches them
+        let shallow = CaveConfig {
+            floor: Some(8),
+            ..CaveConfig::default()
+        };
+        assert_eq!(check(shallow), (24, 53));
+
+        let above = CaveConfig {
+            floor: Some(3),
+            ..CaveConfig::default()
+        };
+        assert_eq!(check(above).1, 1 + 3 + 5);
+
+        // A second source further along adds its own pile
+        let two = CaveConfig {
+            sources: vec![(500, 0), (510, 0)],
+            ..CaveConfig::default()
+        };
+        let (abyss_count, floor_count) = check(two);
+        assert!(abyss_count == 24 && floor_count > 93);
+
+        // Trying right before left mirrors how the sand piles up
+        let mirrored = CaveConfig {
+            rules: vec![(0, 1), (1, 1), (-1, 1)],
+            ..CaveConfig::default()
+        };
+        let mut cave: Cave<Bitmap> = Cave::parse_with(&input, mirrored).unwrap();
+        assert_eq!(cave.drop_sand((500, 0), CaveEnd::Abyss), Some((500, 8)));
+        assert_eq!(cave.drop_sand((500, 0), CaveEnd::Abyss), Some((501, 8)));
+
+        // Sand can also jump two cells sideways at once
+        let wide = CaveConfig {
+            rules: vec![(0, 1), (-2, 1), (2, 1)],
+            ..CaveConfig::default()
+        };
+        let (_, floor_count) = check(wide);
+        assert!(floor_count > 93);
+
+        // Falling several rows at a time can jump well past the abyss
+        let deep = || CaveConfig {
+            rules: vec![(0, 4), (-1, 4), (1, 4)],
+            ..CaveConfig::default()
+        };
+        let counts = check(deep());
+        let mut hashed: Cave<HashCells> = Cave::parse_with(&input, deep()).unwrap();
+        assert_eq!(simulate(&mut hashed), counts);
+
+        let output = super::variant_option(&input, Some("sources=500,0 510,0; floor=8"));
+        assert!(output.starts_with("Abyss: "), "{}", output);
+        assert!(output.contains("Floor at 8: "), "{}", output);
+        let output = super::variant_option(&input, Some("floor=deep"));
+        assert_eq!(output, "Invalid setting \"floor=deep\"");
+    }
 }
@@ -1,15 +1,18 @@
+use std::time::Instant;
+
 struct Map {
     sensor_ranges: Vec<((isize, isize), usize)>,
+    beacons: Vec<(isize, isize)>,
 }
 
 impl Map {
     fn parse(input: &str) -> Map {
-        let sensor_ranges: Vec<((isize, isize), usize)> = input
+        let pairs: Vec<((isize, isize), (isize, isize))> = input
             .lines()
             .map(|line| {
                 let (s_part, b_part) = line.split_once(": ").unwrap();
 
-                let s_xy = s_part.trim_start_matches("Sensor at ");
+                let s_xy = s_part.trim().trim_start_matches("Sensor at ");
                 let b_xy = b_part.trim_start_matches("closest beacon is at ");
 
                 let sensor = Self::parse_xy_str(s_xy);
@@ -17,10 +20,22 @@ impl Map {
 
                 (sensor, beacon)
             })
-            .map(|(sensor, beacon)| (sensor, Self::manhatten(sensor, beacon)))
             .collect();
 
-        Map { sensor_ranges }
+        let sensor_ranges = pairs
+            .iter()
+            .map(|&(sensor, beacon)| (sensor, Self::manhatten(sensor, beacon)))
+            .collect();
+
+        // Several sensors can share a closest beacon
+        let mut beacons: Vec<(isize, isize)> = pairs.iter().map(|&(_, beacon)| beacon).collect();
+        beacons.sort_unstable();
+        beacons.dedup();
+
+        Map {
+            sensor_ranges,
+            beacons,
+        }
     }
 
     fn parse_xy_str(xy_str: &str) -> (isize, isize) {
@@ -69,58 +84,211 @@ impl Map {
         ranges
     }
 
-    fn exclude_zone_size_for(&self, y: isize) -> isize {
-        let ranges = self.intersecting_x_ranges(y, None);
-        let min_x = ranges.iter().map(|range| range.0).min().unwrap();
-        let max_x = ranges.iter().map(|range| range.1).max().unwrap();
-        max_x - min_x
+    // Covered x ranges on a row with overlapping and touching ranges joined
+    fn merged_x_ranges(&self, at_y: isize) -> Vec<(isize, isize)> {
+        let mut merged: Vec<(isize, isize)> = Vec::new();
+
+        for (start, end) in self.intersecting_x_ranges(at_y, None) {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+
+        merged
     }
 
-    fn non_excluded_coord_in_area(&self, area_max: isize) -> (isize, isize) {
+    // Positions on the row that can't hold a beacon, leaving out the
+    // beacons already known to be there
+    fn exclude_zone_size_for(&self, y: isize) -> usize {
+        let ranges = self.merged_x_ranges(y);
+
+        let covered: usize = ranges
+            .iter()
+            .map(|(start, end)| start.abs_diff(*end) + 1)
+            .sum();
+
+        let beacons_on_row = self
+            .beacons
+            .iter()
+            .filter(|(x, beacon_y)| {
+                *beacon_y == y && ranges.iter().any(|(start, end)| (start..=end).contains(&x))
+            })
+            .count();
+
+        covered - beacons_on_row
+    }
+
+    fn is_covered(&self, coords: (isize, isize)) -> bool {
+        self.sensor_ranges
+            .iter()
+            .any(|(sensor, range)| Self::manhatten(*sensor, coords) <= *range)
+    }
+
+    // Finds the uncovered coord by scanning every row, which is slow but
+    // kept to check `uncovered_in_area` against
+    fn non_excluded_coord_in_area(&self, area_max: isize) -> Option<(isize, isize)> {
         for y in 0..=area_max {
-            let intersecting_x_ranges = self.intersecting_x_ranges(y, Some(area_max));
-
-            for (_, end_a) in &intersecting_x_ranges {
-                for (start_b, _) in &intersecting_x_ranges {
-                    // Candidate x after range a
-                    let candidate_x = end_a + 1;
-
-                    // Check if x exists in a gap between range a and range b
-                    if *start_b - 1 != candidate_x {
-                        continue;
-                    }
-
-                    // check if this candidate x is inside the exclusion zone for this y
-                    let x_in_exclude_zone = intersecting_x_ranges
-                        .iter()
-                        .any(|(start, end)| candidate_x >= *start && candidate_x <= *end);
-
-                    if !x_in_exclude_zone {
-                        return (candidate_x, y);
-                    }
+            // The ranges are sorted by start, so the first x past the end of
+            // every range reached so far is either a gap or past the area
+            let mut candidate_x = 0;
+
+            for (start, end) in self.intersecting_x_ranges(y, Some(area_max)) {
+                if start > candidate_x {
+                    break;
                 }
+                candidate_x = candidate_x.max(end + 1);
+            }
+
+            if candidate_x <= area_max {
+                return Some((candidate_x, y));
             }
         }
 
-        (0, 0)
+        None
+    }
+
+    // Finds the uncovered coord from the edges just outside the sensors'
+    // diamonds. A lone uncovered coord has a covered neighbour, so it sits
+    // on one of those edges. Rotating by 45 degrees to u = x + y and
+    // v = x - y turns each edge into a line with fixed u or v, and each
+    // sensor covers a single interval along such a line, so walking the
+    // sorted intervals finds the first gap on it.
+    fn uncovered_in_area(&self, area_max: isize) -> Option<(isize, isize)> {
+        let rotated: Vec<(isize, isize, isize)> = self
+            .sensor_ranges
+            .iter()
+            .map(|&((x, y), range)| (x + y, x - y, range as isize))
+            .collect();
+
+        let mut u_lines: Vec<isize> = Vec::new();
+        let mut v_lines: Vec<isize> = Vec::new();
+
+        for &(u, v, range) in &rotated {
+            u_lines.extend([u - range - 1, u + range + 1]);
+            v_lines.extend([v - range - 1, v + range + 1]);
+        }
+
+        u_lines.sort_unstable();
+        u_lines.dedup();
+        v_lines.sort_unstable();
+        v_lines.dedup();
+
+        let on_u_lines = u_lines.iter().find_map(|&u| {
+            // v range keeping 0 <= x, y <= area_max
+            let lo = (-u).max(u - 2 * area_max);
+            let hi = u.min(2 * area_max - u);
+            let covered = rotated
+                .iter()
+                .filter(|(sensor_u, _, range)| sensor_u.abs_diff(u) as isize <= *range)
+                .map(|&(_, sensor_v, range)| (sensor_v - range, sensor_v + range));
+
+            Self::first_gap(u, lo, hi, covered).map(|v| ((u + v) / 2, (u - v) / 2))
+        });
+
+        let on_v_lines = || {
+            v_lines.iter().find_map(|&v| {
+                let lo = v.abs();
+                let hi = 2 * area_max - v.abs();
+                let covered = rotated
+                    .iter()
+                    .filter(|(_, sensor_v, range)| sensor_v.abs_diff(v) as isize <= *range)
+                    .map(|&(sensor_u, _, range)| (sensor_u - range, sensor_u + range));
+
+                Self::first_gap(v, lo, hi, covered).map(|u| ((u + v) / 2, (u - v) / 2))
+            })
+        };
+
+        // Without any covered neighbours only a one coord area or an empty
+        // map can have a gap, which is then in a corner
+        let in_corner = || {
+            [(0, 0), (area_max, 0), (0, area_max), (area_max, area_max)]
+                .into_iter()
+                .find(|&coords| !self.is_covered(coords))
+        };
+
+        on_u_lines.or_else(on_v_lines).or_else(in_corner)
+    }
+
+    // First position in lo..=hi with the same parity as `line` that none of
+    // the intervals cover. Only those positions are whole coords on the line.
+    fn first_gap(
+        line: isize,
+        lo: isize,
+        hi: isize,
+        covered: impl Iterator<Item = (isize, isize)>,
+    ) -> Option<isize> {
+        let on_line = |pos: isize| pos + (pos - line).rem_euclid(2);
+
+        let mut covered: Vec<(isize, isize)> = covered.collect();
+        covered.sort_unstable();
+
+        let mut pos = on_line(lo);
+
+        for (start, end) in covered {
+            if start > pos {
+                break;
+            }
+            pos = pos.max(on_line(end + 1));
+        }
+
+        (pos <= hi).then_some(pos)
     }
 }
 
-pub fn day15(input: &str) -> (String, String) {
-    let dependant = if cfg!(test) { 10 } else { 2_000_000 };
+// The example uses a smaller row and area than the real input
+fn dependant() -> isize {
+    if cfg!(test) {
+        10
+    } else {
+        2_000_000
+    }
+}
+
+fn tuning_frequency((x, y): (isize, isize)) -> isize {
+    x * 4_000_000 + y
+}
 
+pub fn day15(input: &str) -> (String, String) {
     let map = Map::parse(input);
 
-    let at_y = dependant;
+    let at_y = dependant();
     let part1 = map.exclude_zone_size_for(at_y).to_string();
 
-    let max_area = dependant * 2;
-    let (x, y) = map.non_excluded_coord_in_area(max_area);
-    let part2 = (x * 4_000_000 + y).to_string();
+    let max_area = dependant() * 2;
+    // A fully covered area is a valid map, so report it rather than panic
+    // and leave `--verify` a chance to run
+    let part2 = match map.uncovered_in_area(max_area) {
+        Some(coords) => tuning_frequency(coords).to_string(),
+        None => String::from("none"),
+    };
 
     (part1, part2)
 }
 
+// Checks the part 2 answer against scanning every row
+pub fn verify_option(input: &str, _value: Option<&str>) -> String {
+    let map = Map::parse(input);
+    let max_area = dependant() * 2;
+
+    let timer = Instant::now();
+    let found = map.uncovered_in_area(max_area);
+    let found_time = timer.elapsed();
+
+    let timer = Instant::now();
+    let scanned = map.non_excluded_coord_in_area(max_area);
+    let scanned_time = timer.elapsed();
+
+    format!(
+        "Boundaries: {:?} in {:?}\nRow scan:   {:?} in {:?}\n{}",
+        found.map(tuning_frequency),
+        found_time,
+        scanned.map(tuning_frequency),
+        scanned_time,
+        if found == scanned { "ok" } else { "MISMATCH" }
+    )
+}
+
 #[cfg(test)]
 mod tests {
     const INPUT_LINES: [&str; 14] = [
@@ -148,4 +316,149 @@ mod tests {
         assert_eq!(part1, "26");
         assert_eq!(part2, "56000011");
     }
+
+    #[test]
+    fn test_day15_exclude_zone() {
+        use super::Map;
+
+        let map = Map::parse(&INPUT_LINES.join("\n"));
+        assert_eq!(map.beacons.len(), 6);
+        assert_eq!(map.merged_x_ranges(10), vec![(-2, 24)]);
+        assert_eq!(map.exclude_zone_size_for(10), 26);
+
+        // The row with the distress beacon's gap in it
+        assert_eq!(map.merged_x_ranges(11), vec![(-3, 13), (15, 25)]);
+        assert_eq!(map.exclude_zone_size_for(11), 17 + 11);
+
+        // Disjoint and touching ranges, with beacons on and off the rows
+        let map = Map::parse(
+            "Sensor at x=0, y=0: closest beacon is at x=2, y=0
+             Sensor at x=10, y=0: closest beacon is at x=10, y=1
+             Sensor at x=5, y=3: closest beacon is at x=5, y=5
+             Sensor at x=2, y=2: closest beacon is at x=2, y=3",
+        );
+
+        assert_eq!(map.merged_x_ranges(0), vec![(-2, 2), (9, 11)]);
+        assert_eq!(map.exclude_zone_size_for(0), 5 - 1 + 3);
+
+        assert_eq!(map.merged_x_ranges(1), vec![(-1, 2), (5, 5), (10, 10)]);
+        assert_eq!(map.exclude_zone_size_for(1), 4 + 1 + 1 - 1);
+
+        assert_eq!(map.merged_x_ranges(2), vec![(0, 6)]);
+        assert_eq!(map.exclude_zone_size_for(2), 7);
+
+        assert_eq!(map.merged_x_ranges(3), vec![(2, 7)]);
+        assert_eq!(map.exclude_zone_size_for(3), 6 - 1);
+
+        assert_eq!(map.merged_x_ranges(5), vec![(5, 5)]);
+        assert_eq!(map.exclude_zone_size_for(5), 0);
+
+        assert_eq!(map.merged_x_ranges(-10), vec![]);
+        assert_eq!(map.exclude_zone_size_for(-10), 0);
+    }
+
+    #[test]
+    fn test_day15_uncovered() {
+        use super::Map;
+
+        let map = Map::parse(&INPUT_LINES.join("\n"));
+        assert_eq!(map.uncovered_in_area(20), Some((14, 11)));
+        assert_eq!(map.non_excluded_coord_in_area(20), Some((14, 11)));
+
+        // Gaps in a corner and against an edge of the area
+        let corner = Map {
+            sensor_ranges: vec![((0, 0), 7)],
+            beacons: Vec::new(),
+        };
+        assert_eq!(corner.uncovered_in_area(4), Some((4, 4)));
+
+        let edge = Map {
+            sensor_ranges: vec![((4, 0), 5), ((4, 4), 5)],
+            beacons: Vec::new(),
+        };
+        assert_eq!(edge.uncovered_in_area(4), Some((0, 2)));
+
+        let covered = Map {
+            sensor_ranges: vec![((2, 2), 4)],
+            beacons: Vec::new(),
+        };
+        assert_eq!(covered.uncovered_in_area(4), None);
+    }
+
+    #[test]
+    fn test_day15_uncovered_one_orientation() {
+        use super::Map;
+
+        // The gap at (10, 10) only touches diamond edges with a fixed u, so
+        // it's not where a fixed u edge crosses a fixed v edge
+        let map = Map {
+            sensor_ranges: vec![
+                ((11, 11), 1),
+                ((9, 9), 1),
+                ((13, 7), 4),
+                ((7, 13), 4),
+                ((13, 13), 5),
+                ((7, 7), 5),
+                ((9, 3), 6),
+                ((10, 16), 4),
+                ((11, 20), 9),
+                ((2, 1), 15),
+                ((19, 7), 10),
+                ((1, 16), 13),
+                ((19, 18), 15),
+            ],
+            beacons: Vec::new(),
+        };
+
+        assert_eq!(map.non_excluded_coord_in_area(20), Some((10, 10)));
+        assert_eq!(map.uncovered_in_area(20), Some((10, 10)));
+    }
+
+    #[test]
+    fn test_day15_uncovered_matches_brute_force() {
+        use super::Map;
+
+        let mut state: u64 = 15;
+        let mut random = |max: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % (max + 1)) as isize
+        };
+
+        let mut lone_gaps = 0;
+
+        for _ in 0..500 {
+            let sensor_ranges = (0..12)
+                .map(|_| ((random(44) - 2, random(44) - 2), random(28) as usize))
+                .collect();
+            let map = Map {
+                sensor_ranges,
+                beacons: Vec::new(),
+            };
+
+            let uncovered: Vec<(isize, isize)> = (0..=40)
+                .flat_map(|y| (0..=40).map(move |x| (x, y)))
+                .filter(|&coords| !map.is_covered(coords))
+                .collect();
+
+            // Any gap will do when there are several
+            match map.uncovered_in_area(40) {
+                Some(found) => assert!(uncovered.contains(&found)),
+                None => assert_eq!(uncovered, vec![]),
+            }
+
+            // The row scan finds the first gap in reading order
+            assert_eq!(
+                map.non_excluded_coord_in_area(40),
+                uncovered.first().copied()
+            );
+
+            if uncovered.len() == 1 {
+                lone_gaps += 1;
+            }
+        }
+
+        assert!(lone_gaps > 0);
+    }
 }
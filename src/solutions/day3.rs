@@ -22,8 +22,8 @@ fn part1(rucksacks: Vec<Vec<char>>) -> String {
             (chars.split_off(len / 2), chars)
         })
         .map(|(right, left)| {
-            let left: HashSet<char> = HashSet::from_iter(left.into_iter());
-            let right: HashSet<char> = HashSet::from_iter(right.into_iter());
+            let left: HashSet<char> = HashSet::from_iter(left);
+            let right: HashSet<char> = HashSet::from_iter(right);
 
             *left
                 .intersection(&right)
@@ -68,7 +68,7 @@ fn build_stacks(stack_instructions: &str) -> Vec<Vec<char>> {
 
     for line in lines.into_iter().rev().skip(1) {
         for (i, ch) in line.chars().enumerate() {
-            if ('A'..='Z').contains(&ch) {
+            if ch.is_ascii_uppercase() {
                 let idx = i / 4;
                 stacks[idx].push(ch);
             }
@@ -1,20 +1,22 @@
+use super::render::{self, Viewport};
 use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, PartialEq)]
+enum MotionError {
+    Malformed(String),
+    UnknownDirection(String),
+    InvalidDistance(String),
+}
+
+// Unit step for a direction and the number of times to take it
+type Instruction = ((isize, isize), isize);
 
 pub fn day9(input: &str) -> (String, String) {
-    let instructions: Vec<(char, isize)> = input
-        .trim()
-        .lines()
-        .map(|line| line.trim())
-        .map(|line| line.split_whitespace().collect::<Vec<&str>>())
-        .map(|parts| {
-            (
-                parts[0].chars().next().unwrap(),
-                parts[1].parse::<isize>().unwrap(),
-            )
-        })
-        .collect();
+    let instructions = parse_instructions(input).unwrap();
 
-    let head_positions = head_positions(instructions);
+    let head_positions = head_positions(&instructions);
     let tail_positions_1 = tail_positions(&head_positions);
     let tail_positions_2 = tail_positions(&tail_positions_1);
     let tail_positions_3 = tail_positions(&tail_positions_2);
@@ -25,95 +27,107 @@ pub fn day9(input: &str) -> (String, String) {
     let tail_positions_8 = tail_positions(&tail_positions_7);
     let tail_positions_9 = tail_positions(&tail_positions_8);
 
-    let uniq_tail_positions_1: HashSet<(isize, isize)> =
-        HashSet::from_iter(tail_positions_1.into_iter());
-    let uniq_tail_positions_9: HashSet<(isize, isize)> =
-        HashSet::from_iter(tail_positions_9.into_iter());
+    let uniq_tail_positions_1: HashSet<(isize, isize)> = HashSet::from_iter(tail_positions_1);
+    let uniq_tail_positions_9: HashSet<(isize, isize)> = HashSet::from_iter(tail_positions_9);
     let part1 = uniq_tail_positions_1.len().to_string();
     let part2 = uniq_tail_positions_9.len().to_string();
 
     (part1, part2)
 }
 
-fn tail_positions(head_positions: &Vec<(isize, isize)>) -> Vec<(isize, isize)> {
-    let mut tail_positions: Vec<(isize, isize)> = Vec::new();
+fn parse_instructions(input: &str) -> Result<Vec<Instruction>, MotionError> {
+    input
+        .trim()
+        .lines()
+        .map(|line| line.trim())
+        .map(
+            |line| match line.split_whitespace().collect::<Vec<&str>>()[..] {
+                [dir, dist] => {
+                    let step = direction(dir)?;
+                    let dist = dist
+                        .parse::<isize>()
+                        .ok()
+                        .filter(|dist| *dist >= 0)
+                        .ok_or_else(|| MotionError::InvalidDistance(line.to_string()))?;
 
-    tail_positions.push((0, 0));
+                    Ok((step, dist))
+                }
+                _ => Err(MotionError::Malformed(line.to_string())),
+            },
+        )
+        .collect()
+}
 
-    for head_pos in head_positions {
-        let (head_x, head_y) = head_pos;
-        let &tail_pos = tail_positions.last().unwrap();
-        let (tail_x, tail_y) = tail_pos;
+// Combines any of U, D, L, R into a single step, so "UL" moves diagonally
+fn direction(dir: &str) -> Result<(isize, isize), MotionError> {
+    let unknown = || MotionError::UnknownDirection(dir.to_string());
 
-        let off_x = head_x - tail_x;
-        let off_y = head_y - tail_y;
+    let mut step: (isize, isize) = (0, 0);
 
-        if off_x.abs() <= 1 && off_y.abs() <= 1 {
-            continue;
+    for ch in dir.chars() {
+        let (axis, delta) = match ch {
+            'U' => (&mut step.1, 1),
+            'D' => (&mut step.1, -1),
+            'R' => (&mut step.0, 1),
+            'L' => (&mut step.0, -1),
+            _ => return Err(unknown()),
+        };
+
+        // Reject repeated or opposing letters on the same axis, e.g. "UU" or "LR"
+        if *axis != 0 {
+            return Err(unknown());
         }
 
-        // Part 1
-        // ...HHH.....
-        // ..H...H....
-        // .H..T..H...
-        // ..H...H....
-        // ...HHH.....
-
-        // Part 2
-        // ..H...H....
-        // ...........
-        // ....T......
-        // ...........
-        // ..H...H....
-        let new_tail_pos = match (off_x, off_y) {
-            // Part 1
-            (0, 2) => (tail_x, tail_y + 1),
-            (0, -2) => (tail_x, tail_y - 1),
-
-            (2, 0) => (tail_x + 1, tail_y),
-            (-2, 0) => (tail_x - 1, tail_y),
-
-            (2, 1) => (tail_x + 1, tail_y + 1),
-            (1, 2) => (tail_x + 1, tail_y + 1),
-
-            (-2, 1) => (tail_x - 1, tail_y + 1),
-            (-1, 2) => (tail_x - 1, tail_y + 1),
-
-            (-2, -1) => (tail_x - 1, tail_y - 1),
-            (-1, -2) => (tail_x - 1, tail_y - 1),
-
-            (2, -1) => (tail_x + 1, tail_y - 1),
-            (1, -2) => (tail_x + 1, tail_y - 1),
-
-            // Part 2
-            (2, 2) => (tail_x + 1, tail_y + 1),
-            (-2, 2) => (tail_x - 1, tail_y + 1),
-            (2, -2) => (tail_x + 1, tail_y - 1),
-            (-2, -2) => (tail_x - 1, tail_y - 1),
-            _ => panic!(),
-        };
+        *axis = delta;
+    }
 
-        tail_positions.push(new_tail_pos);
+    if step == (0, 0) {
+        return Err(unknown());
+    }
+
+    Ok(step)
+}
+
+fn touching(head: (isize, isize), tail: (isize, isize)) -> bool {
+    (head.0 - tail.0).abs() <= 1 && (head.1 - tail.1).abs() <= 1
+}
+
+// Move one cell towards the head along each axis the head is offset on
+fn follow(head: (isize, isize), tail: (isize, isize)) -> (isize, isize) {
+    let (off_x, off_y) = (head.0 - tail.0, head.1 - tail.1);
+
+    (tail.0 + off_x.signum(), tail.1 + off_y.signum())
+}
+
+fn tail_positions(head_positions: &[(isize, isize)]) -> Vec<(isize, isize)> {
+    let mut tail_positions: Vec<(isize, isize)> = Vec::new();
+
+    tail_positions.push((0, 0));
+
+    for &head_pos in head_positions {
+        let mut tail_pos = *tail_positions.last().unwrap();
+
+        // Heads that jumped more than one cell are chased one step at a time
+        while !touching(head_pos, tail_pos) {
+            tail_pos = follow(head_pos, tail_pos);
+            tail_positions.push(tail_pos);
+        }
     }
 
     tail_positions
 }
 
-fn head_positions(instructions: Vec<(char, isize)>) -> Vec<(isize, isize)> {
+fn head_positions(instructions: &[Instruction]) -> Vec<(isize, isize)> {
     let mut head_positions: Vec<(isize, isize)> = Vec::new();
 
     head_positions.push((0, 0));
 
-    for (dir, dist) in instructions {
+    for &((step_x, step_y), dist) in instructions {
         let &(x, y) = head_positions.last().unwrap();
 
-        let mut movements: Vec<(isize, isize)> = match dir {
-            'U' => ((y + 1)..=(y + dist)).map(|y| (x, y)).collect(),
-            'D' => ((y - dist)..y).rev().map(|y| (x, y)).collect(),
-            'R' => ((x + 1)..=(x + dist)).map(|x| (x, y)).collect(),
-            'L' => ((x - dist)..x).rev().map(|x| (x, y)).collect(),
-            _ => panic!(),
-        };
+        let mut movements: Vec<(isize, isize)> = (1..=dist)
+            .map(|i| (x + step_x * i, y + step_y * i))
+            .collect();
 
         head_positions.append(&mut movements);
     }
@@ -121,6 +135,176 @@ fn head_positions(instructions: Vec<(char, isize)>) -> Vec<(isize, isize)> {
     head_positions
 }
 
+// Smallest viewport containing the start and every knot in every frame
+fn fit(frames: &[Vec<(isize, isize)>]) -> Viewport {
+    Viewport::around((0, 0), frames.iter().flatten().copied())
+}
+
+// Position of every knot after each head step, head first. Frame 0 is the
+// starting position with the whole rope on (0, 0).
+fn rope_frames(head_positions: &[(isize, isize)], knots: usize) -> Vec<Vec<(isize, isize)>> {
+    let mut rope: Vec<(isize, isize)> = vec![(0, 0); knots];
+    let mut frames: Vec<Vec<(isize, isize)>> = vec![rope.clone()];
+
+    for &head_pos in head_positions.iter().skip(1) {
+        rope[0] = head_pos;
+
+        // Cells the previous knot passed through this step, so a knot that
+        // moved several cells is followed the same way tail_positions does
+        let mut leader_path: Vec<(isize, isize)> = vec![head_pos];
+
+        for knot in rope.iter_mut().skip(1) {
+            let mut knot_path: Vec<(isize, isize)> = Vec::new();
+
+            for &leader in &leader_path {
+                while !touching(leader, *knot) {
+                    *knot = follow(leader, *knot);
+                    knot_path.push(*knot);
+                }
+            }
+
+            leader_path = knot_path;
+        }
+
+        frames.push(rope.clone());
+    }
+
+    frames
+}
+
+fn knot_label(knot: usize, knots: usize) -> char {
+    match knot {
+        0 => 'H',
+        _ if knots == 2 => 'T',
+        n => char::from_digit(n as u32 % 36, 36).unwrap(),
+    }
+}
+
+// Draws a frame the way the puzzle does, with y pointing up and earlier knots
+// covering later ones
+fn render_frame(
+    rope: &[(isize, isize)],
+    trail: &HashSet<(isize, isize)>,
+    viewport: Viewport,
+) -> String {
+    let mut out = String::new();
+
+    for y in (viewport.min.1..=viewport.max.1).rev() {
+        for x in viewport.min.0..=viewport.max.0 {
+            let coords = (x, y);
+            let ch = match rope.iter().position(|knot| *knot == coords) {
+                Some(knot) => knot_label(knot, rope.len()),
+                None if coords == (0, 0) => 's',
+                None if trail.contains(&coords) => '#',
+                None => '.',
+            };
+            out.push(ch);
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+// Binary PPM with each cell drawn as a `scale` sized square
+fn render_frame_ppm(
+    rope: &[(isize, isize)],
+    trail: &HashSet<(isize, isize)>,
+    viewport: Viewport,
+    scale: usize,
+) -> Vec<u8> {
+    let cols = viewport.width();
+
+    // Colour each cell once, later layers painting over earlier ones
+    let mut cells: Vec<[u8; 3]> = vec![[20, 20, 20]; cols * viewport.height()];
+
+    let mut paint = |(x, y): (isize, isize), rgb: [u8; 3]| {
+        if !viewport.contains((x, y)) {
+            return;
+        }
+        let col = x.abs_diff(viewport.min.0);
+        let row = y.abs_diff(viewport.max.1);
+        cells[row * cols + col] = rgb;
+    };
+
+    for &coords in trail {
+        paint(coords, [110, 110, 110]);
+    }
+
+    paint((0, 0), [60, 200, 60]);
+
+    // Fade from yellow to blue along the rope, with the head in red on top
+    for (knot, &coords) in rope.iter().enumerate().rev() {
+        let t = (knot * 255 / rope.len().max(2).saturating_sub(1)) as u8;
+        let rgb = if knot == 0 {
+            [230, 60, 60]
+        } else {
+            [255 - t, 220 - t / 2, t]
+        };
+        paint(coords, rgb);
+    }
+
+    render::ppm(&cells, cols, scale)
+}
+
+pub fn frames_option(input: &str, value: Option<&str>) -> String {
+    let instructions = match parse_instructions(input) {
+        Ok(instructions) => instructions,
+        Err(err) => return format!("{:?}", err),
+    };
+
+    let frames = rope_frames(&head_positions(&instructions), 10);
+    let mut trail: HashSet<(isize, isize)> = HashSet::new();
+
+    let viewport = match value.map(Viewport::parse) {
+        None => fit(&frames),
+        Some(Ok(viewport)) => viewport,
+        Some(Err(err)) => return err,
+    };
+
+    frames
+        .iter()
+        .enumerate()
+        .map(|(i, rope)| {
+            trail.insert(*rope.last().unwrap());
+            format!(
+                "== Frame {} ==\n\n{}",
+                i,
+                render_frame(rope, &trail, viewport)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+pub fn ppm_option(input: &str, value: Option<&str>) -> String {
+    let dir = Path::new(value.unwrap_or("frames"));
+
+    let instructions = match parse_instructions(input) {
+        Ok(instructions) => instructions,
+        Err(err) => return format!("{:?}", err),
+    };
+
+    let frames = rope_frames(&head_positions(&instructions), 10);
+    let mut trail: HashSet<(isize, isize)> = HashSet::new();
+    let viewport = fit(&frames);
+
+    if let Err(err) = fs::create_dir_all(dir) {
+        return format!("Could not create {}: {}", dir.display(), err);
+    }
+
+    for (i, rope) in frames.iter().enumerate() {
+        let path = dir.join(format!("frame_{:05}.ppm", i));
+        trail.insert(*rope.last().unwrap());
+
+        if let Err(err) = fs::write(&path, render_frame_ppm(rope, &trail, viewport, 2)) {
+            return format!("Could not write {}: {}", path.display(), err);
+        }
+    }
+
+    format!("Wrote {} frames to {}", frames.len(), dir.display())
+}
+
 #[cfg(test)]
 mod tests {
     const INPUTS: [&str; 2] = [
@@ -154,4 +338,69 @@ mod tests {
         assert_eq!(part1, "88");
         assert_eq!(part2, "36");
     }
+
+    #[test]
+    fn test_day9_diagonal_instructions() {
+        use super::{head_positions, parse_instructions, tail_positions};
+
+        let instructions = parse_instructions("UR 3\nDL 1").unwrap();
+        assert_eq!(instructions, vec![((1, 1), 3), ((-1, -1), 1)]);
+
+        let heads = head_positions(&instructions);
+        assert_eq!(heads, vec![(0, 0), (1, 1), (2, 2), (3, 3), (2, 2)]);
+        assert_eq!(tail_positions(&heads), vec![(0, 0), (1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn test_day9_multi_cell_jump() {
+        use super::tail_positions;
+
+        let heads = vec![(0, 0), (4, 2)];
+        assert_eq!(tail_positions(&heads), vec![(0, 0), (1, 1), (2, 2), (3, 2)]);
+    }
+
+    #[test]
+    fn test_day9_invalid_motion() {
+        use super::parse_instructions;
+        use super::MotionError::{InvalidDistance, Malformed, UnknownDirection};
+
+        let err = parse_instructions("X 1").unwrap_err();
+        assert_eq!(err, UnknownDirection("X".to_string()));
+
+        let err = parse_instructions("LR 1").unwrap_err();
+        assert_eq!(err, UnknownDirection("LR".to_string()));
+
+        let err = parse_instructions("U -2").unwrap_err();
+        assert_eq!(err, InvalidDistance("U -2".to_string()));
+
+        let err = parse_instructions("U").unwrap_err();
+        assert_eq!(err, Malformed("U".to_string()));
+    }
+
+    #[test]
+    fn test_day9_frames() {
+        use super::{head_positions, parse_instructions, render_frame, rope_frames};
+        use super::{tail_positions, Viewport};
+        use std::collections::HashSet;
+
+        let heads = head_positions(&parse_instructions(INPUTS[0]).unwrap());
+        let viewport = Viewport::parse("0,0,5,4").unwrap();
+
+        // After the first "R 4"
+        let frames = rope_frames(&heads, 10);
+        let frame = render_frame(&frames[4], &HashSet::new(), viewport);
+        assert_eq!(frame, "......\n......\n......\n......\n4321H.\n");
+
+        let frames = rope_frames(&heads, 2);
+        let frame = render_frame(&frames[4], &HashSet::new(), viewport);
+        assert_eq!(frame, "......\n......\n......\n......\ns..TH.\n");
+
+        // Visited cells with the rope hidden
+        let trail: HashSet<(isize, isize)> = frames.iter().map(|rope| rope[1]).collect();
+        let visited = render_frame(&[], &trail, viewport);
+        assert_eq!(visited, "..##..\n...##.\n.####.\n....#.\ns###..\n");
+
+        let expected: HashSet<(isize, isize)> = HashSet::from_iter(tail_positions(&heads));
+        assert_eq!(trail, expected);
+    }
 }
@@ -0,0 +1,104 @@
+// Capital letters from the 4x6 font the puzzles draw their answers in. The
+// font has no glyphs for D, M, N, Q, T, V, W or X.
+const GLYPHS: [(char, [&str; 6]); 17] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 6;
+
+// Letters sit in 5 pixel wide cells, the last column being spacing
+const CELL_WIDTH: usize = GLYPH_WIDTH + 1;
+
+// Reads the letters drawn on a 6 row bitmap, using '?' for any cell that
+// isn't a known glyph
+pub fn read_letters<R: AsRef<[bool]>>(rows: &[R]) -> String {
+    if rows.len() != GLYPH_HEIGHT {
+        return String::from("?");
+    }
+
+    let width = rows.iter().map(|row| row.as_ref().len()).max().unwrap_or(0);
+
+    (0..(width + 1) / CELL_WIDTH)
+        .map(|cell| read_letter(rows, cell * CELL_WIDTH))
+        .collect()
+}
+
+fn read_letter<R: AsRef<[bool]>>(rows: &[R], start_x: usize) -> char {
+    let pixel = |x: usize, y: usize| *rows[y].as_ref().get(start_x + x).unwrap_or(&false);
+
+    GLYPHS
+        .iter()
+        .find(|(_, glyph)| {
+            glyph.iter().enumerate().all(|(y, glyph_row)| {
+                glyph_row
+                    .chars()
+                    .enumerate()
+                    .all(|(x, ch)| pixel(x, y) == (ch == '#'))
+            })
+        })
+        .map(|(letter, _)| *letter)
+        .unwrap_or('?')
+}
+
+#[cfg(test)]
+mod tests {
+    fn to_rows(lines: [&str; 6]) -> Vec<Vec<bool>> {
+        lines
+            .iter()
+            .map(|line| line.chars().map(|ch| ch == '#').collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_ocr_read_letters() {
+        use super::read_letters;
+
+        let rows = to_rows([
+            "#..#.####.#....#.....##..",
+            "#..#.#....#....#....#..#.",
+            "####.###..#....#....#..#.",
+            "#..#.#....#....#....#..#.",
+            "#..#.#....#....#....#..#.",
+            "#..#.####.####.####..##..",
+        ]);
+        assert_eq!(read_letters(&rows), "HELLO");
+
+        // Trailing spacing column may be cut off
+        let rows = to_rows(["###.", "#..#", "#..#", "###.", "#...", "#..."]);
+        assert_eq!(read_letters(&rows), "P");
+    }
+
+    #[test]
+    fn test_ocr_unknown_glyph() {
+        use super::read_letters;
+
+        let rows = to_rows([
+            "#...#.####.",
+            "##.##....#.",
+            "#.#.#...#..",
+            "#...#..#...",
+            "#...#.#....",
+            "#...#.####.",
+        ]);
+        assert_eq!(read_letters(&rows), "??");
+
+        assert_eq!(read_letters(&[vec![true]]), "?");
+    }
+}
@@ -0,0 +1,127 @@
+// Inclusive bounds of the part of a grid that gets drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub min: (isize, isize),
+    pub max: (isize, isize),
+}
+
+impl Viewport {
+    // From a `min_x,min_y,max_x,max_y` option value, with the error being
+    // the message to show for it
+    pub fn parse(value: &str) -> Result<Viewport, String> {
+        let nums = value
+            .split(',')
+            .map(|num| num.trim().parse::<isize>().ok())
+            .collect::<Option<Vec<isize>>>();
+
+        match nums.as_deref() {
+            Some(&[min_x, min_y, max_x, max_y]) if min_x <= max_x && min_y <= max_y => {
+                Ok(Viewport {
+                    min: (min_x, min_y),
+                    max: (max_x, max_y),
+                })
+            }
+            _ => Err(format!(
+                "Invalid viewport {:?}, expected min_x,min_y,max_x,max_y",
+                value
+            )),
+        }
+    }
+
+    // Smallest viewport containing `start` and every other point
+    pub fn around(
+        start: (isize, isize),
+        points: impl IntoIterator<Item = (isize, isize)>,
+    ) -> Viewport {
+        points.into_iter().fold(
+            Viewport {
+                min: start,
+                max: start,
+            },
+            |viewport, (x, y)| Viewport {
+                min: (viewport.min.0.min(x), viewport.min.1.min(y)),
+                max: (viewport.max.0.max(x), viewport.max.1.max(y)),
+            },
+        )
+    }
+
+    pub fn width(&self) -> usize {
+        self.min.0.abs_diff(self.max.0) + 1
+    }
+
+    pub fn height(&self) -> usize {
+        self.min.1.abs_diff(self.max.1) + 1
+    }
+
+    pub fn contains(&self, (x, y): (isize, isize)) -> bool {
+        (self.min.0..=self.max.0).contains(&x) && (self.min.1..=self.max.1).contains(&y)
+    }
+
+    // Every point row by row, from min y down to max y
+    pub fn cells(&self) -> impl Iterator<Item = (isize, isize)> + '_ {
+        (self.min.1..=self.max.1).flat_map(|y| (self.min.0..=self.max.0).map(move |x| (x, y)))
+    }
+}
+
+// Binary PPM of `cols` wide rows of colours, with each cell drawn as a
+// `scale` sized square
+pub fn ppm(cells: &[[u8; 3]], cols: usize, scale: usize) -> Vec<u8> {
+    let rows = cells.len() / cols;
+    let mut out: Vec<u8> = format!("P6\n{} {}\n255\n", cols * scale, rows * scale).into_bytes();
+
+    for cell_row in cells.chunks(cols) {
+        let line: Vec<u8> = cell_row.iter().flat_map(|rgb| rgb.repeat(scale)).collect();
+
+        for _ in 0..scale {
+            out.extend_from_slice(&line);
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_render_viewport() {
+        use super::Viewport;
+
+        let viewport = Viewport::parse("-2, 0,3,1").unwrap();
+        assert_eq!(viewport.min, (-2, 0));
+        assert_eq!(viewport.max, (3, 1));
+        assert_eq!((viewport.width(), viewport.height()), (6, 2));
+        assert!(viewport.contains((-2, 1)) && !viewport.contains((4, 0)));
+        assert_eq!(viewport.cells().nth(6), Some((-2, 1)));
+
+        for value in ["1,2,3", "3,0,1,0", "a,0,1,1"] {
+            assert_eq!(
+                Viewport::parse(value),
+                Err(format!(
+                    "Invalid viewport {:?}, expected min_x,min_y,max_x,max_y",
+                    value
+                ))
+            );
+        }
+
+        let viewport = Viewport::around((0, 0), [(3, -1), (-2, 4)]);
+        assert_eq!((viewport.min, viewport.max), ((-2, -1), (3, 4)));
+        assert_eq!(Viewport::around((5, 5), []).width(), 1);
+    }
+
+    #[test]
+    fn test_render_ppm() {
+        let (red, blue) = ([255, 0, 0], [0, 0, 255]);
+        let ppm = super::ppm(&[red, blue, blue, red], 2, 2);
+
+        let header = b"P6\n4 4\n255\n";
+        assert!(ppm.starts_with(header));
+
+        let pixels: Vec<[u8; 3]> = ppm[header.len()..]
+            .chunks(3)
+            .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+            .collect();
+        let row_1 = [red, red, blue, blue];
+        let row_2 = [blue, blue, red, red];
+        assert_eq!(pixels, [row_1, row_1, row_2, row_2].concat());
+    }
+}
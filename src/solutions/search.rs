@@ -0,0 +1,257 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::hash::Hash;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Found<N> {
+    // From the start the search reached the goal from, to the goal
+    pub path: Vec<N>,
+    pub cost: usize,
+}
+
+// Walks predecessors back from `end`, returning the path from the start
+fn reconstruct_path<N: Clone + Eq + Hash>(came_from: &HashMap<N, Option<N>>, end: N) -> Vec<N> {
+    let mut path: Vec<N> = vec![end];
+
+    while let Some(Some(previous)) = came_from.get(path.last().unwrap()) {
+        path.push(previous.clone());
+    }
+
+    path.reverse();
+    path
+}
+
+// Breadth first search where every edge costs 1
+pub fn bfs<N, I>(
+    starts: impl IntoIterator<Item = N>,
+    mut neighbours: impl FnMut(&N) -> I,
+    mut is_goal: impl FnMut(&N) -> bool,
+) -> Option<Found<N>>
+where
+    N: Clone + Eq + Hash,
+    I: IntoIterator<Item = N>,
+{
+    let mut open: VecDeque<(N, usize)> = VecDeque::new();
+
+    // Where each seen node was first reached from
+    let mut came_from: HashMap<N, Option<N>> = HashMap::new();
+
+    for start in starts {
+        if !came_from.contains_key(&start) {
+            came_from.insert(start.clone(), None);
+            open.push_back((start, 0));
+        }
+    }
+
+    while let Some((current, cost)) = open.pop_front() {
+        if is_goal(&current) {
+            let path = reconstruct_path(&came_from, current);
+            return Some(Found { path, cost });
+        }
+
+        for next in neighbours(&current) {
+            if !came_from.contains_key(&next) {
+                came_from.insert(next.clone(), Some(current.clone()));
+                open.push_back((next, cost + 1));
+            }
+        }
+    }
+
+    None
+}
+
+// Steps from the nearest start to every reachable node
+pub fn distances<N, I>(
+    starts: impl IntoIterator<Item = N>,
+    mut neighbours: impl FnMut(&N) -> I,
+) -> HashMap<N, usize>
+where
+    N: Clone + Eq + Hash,
+    I: IntoIterator<Item = N>,
+{
+    let mut open: VecDeque<N> = VecDeque::new();
+    let mut dists: HashMap<N, usize> = HashMap::new();
+
+    for start in starts {
+        if !dists.contains_key(&start) {
+            dists.insert(start.clone(), 0);
+            open.push_back(start);
+        }
+    }
+
+    while let Some(current) = open.pop_front() {
+        let dist = dists[&current];
+
+        for next in neighbours(&current) {
+            if !dists.contains_key(&next) {
+                dists.insert(next.clone(), dist + 1);
+                open.push_back(next);
+            }
+        }
+    }
+
+    dists
+}
+
+// Only the tests use this; elsewhere call `astar` with `|_| 0`
+#[cfg(test)]
+pub fn dijkstra<N, I>(
+    starts: impl IntoIterator<Item = N>,
+    neighbours: impl FnMut(&N) -> I,
+    cost: impl FnMut(&N, &N) -> usize,
+    is_goal: impl FnMut(&N) -> bool,
+) -> Option<Found<N>>
+where
+    N: Clone + Eq + Hash,
+    I: IntoIterator<Item = N>,
+{
+    astar(starts, neighbours, cost, is_goal, |_| 0)
+}
+
+// The heuristic must never overestimate the remaining cost to a goal for the
+// path found to be the cheapest. A heuristic of `|_| 0` makes this Dijkstra.
+pub fn astar<N, I>(
+    starts: impl IntoIterator<Item = N>,
+    mut neighbours: impl FnMut(&N) -> I,
+    mut cost: impl FnMut(&N, &N) -> usize,
+    mut is_goal: impl FnMut(&N) -> bool,
+    mut heuristic: impl FnMut(&N) -> usize,
+) -> Option<Found<N>>
+where
+    N: Clone + Eq + Hash,
+    I: IntoIterator<Item = N>,
+{
+    // Heap entries refer to nodes by index so nodes don't need to be Ord
+    let mut nodes: Vec<N> = Vec::new();
+    let mut open: BinaryHeap<Reverse<(usize, usize, usize)>> = BinaryHeap::new();
+
+    let mut best: HashMap<N, usize> = HashMap::new();
+    let mut came_from: HashMap<N, Option<N>> = HashMap::new();
+
+    for start in starts {
+        if best.insert(start.clone(), 0).is_none() {
+            came_from.insert(start.clone(), None);
+            open.push(Reverse((heuristic(&start), 0, nodes.len())));
+            nodes.push(start);
+        }
+    }
+
+    while let Some(Reverse((_, current_cost, idx))) = open.pop() {
+        let current = nodes[idx].clone();
+
+        // Skip entries superseded by a cheaper route
+        if best[&current] < current_cost {
+            continue;
+        }
+
+        if is_goal(&current) {
+            let path = reconstruct_path(&came_from, current);
+            return Some(Found {
+                path,
+                cost: current_cost,
+            });
+        }
+
+        for next in neighbours(&current) {
+            let next_cost = current_cost + cost(&current, &next);
+
+            if best.get(&next).is_some_and(|&known| known <= next_cost) {
+                continue;
+            }
+
+            best.insert(next.clone(), next_cost);
+            came_from.insert(next.clone(), Some(current.clone()));
+            open.push(Reverse((
+                next_cost + heuristic(&next),
+                next_cost,
+                nodes.len(),
+            )));
+            nodes.push(next);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    // Digits are the cost of entering a cell, # is a wall
+    const GRID: [&str; 5] = ["11111", "1###1", "19191", "1#1#1", "11111"];
+
+    fn neighbours(&(x, y): &(isize, isize)) -> Vec<(isize, isize)> {
+        [(0, 1), (0, -1), (1, 0), (-1, 0)]
+            .iter()
+            .map(|(dx, dy)| (x + dx, y + dy))
+            .filter(|&(x, y)| cell(x, y).is_some_and(|ch| ch != '#'))
+            .collect()
+    }
+
+    fn cell(x: isize, y: isize) -> Option<char> {
+        let row = GRID.get(usize::try_from(y).ok()?)?;
+        row.chars().nth(usize::try_from(x).ok()?)
+    }
+
+    fn enter_cost(_: &(isize, isize), &(x, y): &(isize, isize)) -> usize {
+        cell(x, y).unwrap().to_digit(10).unwrap() as usize
+    }
+
+    #[test]
+    fn test_search_bfs() {
+        use super::bfs;
+
+        let found = bfs([(0, 0)], neighbours, |&node| node == (2, 2)).unwrap();
+        assert_eq!(found.cost, 4);
+        assert_eq!(found.path.len(), 5);
+        assert_eq!(found.path.first(), Some(&(0, 0)));
+        assert_eq!(found.path.last(), Some(&(2, 2)));
+
+        // Nearest of several starts
+        let found = bfs([(4, 4), (0, 2)], neighbours, |&node| node == (0, 0)).unwrap();
+        assert_eq!(found.path, vec![(0, 2), (0, 1), (0, 0)]);
+
+        assert_eq!(bfs([(0, 0)], neighbours, |&node| node == (1, 1)), None);
+    }
+
+    #[test]
+    fn test_search_distances() {
+        use super::distances;
+
+        let dists = distances([(0, 0)], neighbours);
+        assert_eq!(dists.len(), 20);
+        assert_eq!(dists[&(0, 0)], 0);
+        assert_eq!(dists[&(2, 2)], 4);
+        assert_eq!(dists[&(4, 4)], 8);
+        assert!(!dists.contains_key(&(1, 1)));
+
+        // Each node measured from its nearest start
+        let dists = distances([(0, 0), (4, 4)], neighbours);
+        assert_eq!(dists[&(4, 0)], 4);
+        assert_eq!(dists[&(2, 4)], 2);
+    }
+
+    #[test]
+    fn test_search_weighted() {
+        use super::{astar, dijkstra};
+
+        // Straight through the 9 costs more than going round
+        let found = dijkstra([(2, 0)], neighbours, enter_cost, |&node| node == (2, 4)).unwrap();
+        assert_eq!(found.cost, 8);
+        assert!(!found.path.contains(&(2, 2)));
+
+        let manhattan = |&(x, y): &(isize, isize)| x.abs_diff(2) + y.abs_diff(4);
+        let found_astar = astar(
+            [(2, 0)],
+            neighbours,
+            enter_cost,
+            |&node| node == (2, 4),
+            manhattan,
+        )
+        .unwrap();
+        assert_eq!(found_astar.cost, found.cost);
+
+        assert_eq!(
+            dijkstra([(0, 0)], neighbours, enter_cost, |&node| node == (1, 1)),
+            None
+        );
+    }
+}